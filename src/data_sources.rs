@@ -1,4 +1,15 @@
 pub(crate) mod auth;
+pub(crate) mod cook_log;
 pub(crate) mod dishes;
+pub(crate) mod dynamodb;
 pub(crate) mod ingredients;
+pub(crate) mod meal_plans;
+pub(crate) mod memory;
+pub(crate) mod paging;
+pub(crate) mod pantry;
+pub(crate) mod photo_dir;
+pub(crate) mod s3;
+pub(crate) mod search;
 pub(crate) mod shares;
+pub(crate) mod sqlite;
+pub(crate) mod store;
@@ -1,37 +1,25 @@
+use std::sync::Arc;
+
 use anyhow::Error;
 use argon2::{Argon2, PasswordHash, PasswordVerifier};
-use aws_sdk_dynamodb::{model::AttributeValue, Client};
 use biscuit_auth::{Biscuit, KeyPair, PrivateKey};
-use serde::Deserialize;
-use serde_dynamo::from_item;
 
+use super::store::UserStore;
 use crate::graphql::LoginResult;
 
-const TABLE_NAME: &str = "todays-menu-users";
-
 pub(crate) struct Authenticator {
-    db_client: Client,
+    users: Arc<dyn UserStore>,
     root_key: KeyPair,
 }
 
-#[derive(Deserialize)]
-struct UserRecord {
-    user_id: String,
-    roles: Vec<String>,
-    hashed_password: String,
-}
-
 impl Authenticator {
-    pub(crate) fn new(dynamodb: Client, root_key_hex: &str) -> Authenticator {
+    pub(crate) fn new(users: Arc<dyn UserStore>, root_key_hex: &str) -> Authenticator {
         let root_key_bytes = hex::decode(root_key_hex).expect("invalid root key hexstring");
         let root_key = KeyPair::from(
             PrivateKey::from_bytes(&root_key_bytes).expect("invalid root key hexstring"),
         );
 
-        Authenticator {
-            db_client: dynamodb,
-            root_key,
-        }
+        Authenticator { users, root_key }
     }
 
     pub(crate) async fn authenticate(
@@ -39,17 +27,9 @@ impl Authenticator {
         user_id: String,
         password: String,
     ) -> Result<LoginResult, Error> {
-        let output = self
-            .db_client
-            .get_item()
-            .table_name(TABLE_NAME)
-            .key("user_id", AttributeValue::S(user_id.clone()))
-            .send()
-            .await?;
-
-        let user: UserRecord = match output.item.map(from_item).transpose() {
-            Ok(Some(user)) => user,
-            _ => {
+        let user = match self.users.get(&user_id).await? {
+            Some(user) => user,
+            None => {
                 let mut builder = Biscuit::builder(&self.root_key);
                 builder.add_authority_fact(format!("user(\"{}\")", user_id).as_str())?;
                 let token = builder.build()?.to_base64()?;
@@ -0,0 +1,94 @@
+use std::{cmp::Reverse, collections::HashMap, sync::Arc};
+
+use anyhow::Error;
+use async_graphql::dataloader::Loader;
+use chrono::NaiveDate;
+
+use super::store::CookLogStore;
+use crate::graphql::{CookEntry, Meal};
+
+/// How often and how recently a dish was cooked.
+#[derive(Clone, Default)]
+pub(crate) struct CookStats {
+    pub last_cooked: Option<NaiveDate>,
+    pub times_cooked: u32,
+}
+
+pub(crate) struct CookLogLoader {
+    store: Arc<dyn CookLogStore>,
+}
+
+#[async_trait::async_trait]
+impl Loader<String> for CookLogLoader {
+    type Value = CookStats;
+    type Error = Arc<Error>;
+
+    /// Dishes never cooked get empty stats rather than no value.
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, CookStats>, Self::Error> {
+        let entries = self.store.get_for_dishes(keys).await.map_err(Arc::new)?;
+        let mut stats: HashMap<String, CookStats> = keys
+            .iter()
+            .map(|key| (key.clone(), CookStats::default()))
+            .collect();
+        for entry in entries {
+            let dish = stats.entry(entry.dish_name).or_default();
+            dish.times_cooked += 1;
+            dish.last_cooked = dish.last_cooked.max(Some(entry.date));
+        }
+        Ok(stats)
+    }
+}
+
+impl CookLogLoader {
+    pub(crate) fn new(store: Arc<dyn CookLogStore>) -> CookLogLoader {
+        CookLogLoader { store }
+    }
+    /// Returns the entries that were not recorded before.
+    pub(crate) async fn record(
+        &self,
+        date: NaiveDate,
+        meal: Meal,
+        dish_names: &[String],
+    ) -> Result<Vec<CookEntry>, Error> {
+        let entries: Vec<CookEntry> = dish_names
+            .iter()
+            .map(|dish_name| CookEntry {
+                dish_name: dish_name.clone(),
+                date,
+                meal,
+            })
+            .collect();
+        self.store.put_many(&entries).await
+    }
+    /// Entries from `from` to `to`, both included, newest first.
+    pub(crate) async fn history(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        dish_name: Option<String>,
+    ) -> Result<Vec<CookEntry>, Error> {
+        let mut entries = match dish_name {
+            Some(dish_name) => {
+                let mut entries = self.store.get_for_dishes(&[dish_name]).await?;
+                entries.retain(|entry| from <= entry.date && entry.date <= to);
+                entries
+            }
+            None => self.store.get_range(from, to).await?,
+        };
+        entries.sort_by_key(|entry| Reverse((entry.date, entry.meal)));
+        Ok(entries)
+    }
+    /// The day each dish cooked from `from` to `to` was cooked last.
+    pub(crate) async fn last_cooked(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<HashMap<String, NaiveDate>, Error> {
+        let mut last_cooked: HashMap<String, NaiveDate> = HashMap::new();
+        for entry in self.store.get_range(from, to).await? {
+            let date = last_cooked.entry(entry.dish_name).or_insert(entry.date);
+            *date = entry.date.max(*date);
+        }
+        Ok(last_cooked)
+    }
+}
@@ -1,51 +1,23 @@
 use std::{collections::HashMap, sync::Arc};
 
 use anyhow::Error;
-use async_graphql::{dataloader::Loader, Context};
-use aws_sdk_dynamodb::{
-    model::{AttributeValue, KeysAndAttributes},
-    Client as DynamoDbClient,
-};
-use aws_sdk_s3::{types::ByteStream, Client as S3Client};
+use async_graphql::{dataloader::Loader, Context, MaybeUndefined, Upload};
 use nanoid::nanoid;
-use serde_dynamo::{from_items, to_attribute_value};
-use tokio::fs::File;
-
-use crate::graphql::{Dish, DishInput, Photo};
-
-const TABLE_NAME: &str = "todays-menu-dishes";
-const S3_BUCKET: &str = "todays-menu-photos";
-
-const UPDATE_EXP: &str = "SET
-    meal = :meal,
-    ingredients = :ingredients,
-    spicy = :spicy,
-    cook_time = :cook_time,
-    recipe_link = :recipe_link,
-    serving = :serving,
-    one_dish = :one_dish,
-    soup = :soup,
-    #st = :style,
-    suppressed = :suppressed
-";
 
-const UPDATE_EXP_WITH_PHOTO: &str = "SET
-    meal = :meal,
-    ingredients = :ingredients,
-    spicy = :spicy,
-    cook_time = :cook_time,
-    recipe_link = :recipe_link,
-    serving = :serving,
-    one_dish = :one_dish,
-    soup = :soup,
-    #st = :style,
-    suppressed = :suppressed,
-    photo = :photo
-";
+use super::{
+    paging::{slice, walk, Window},
+    search::SearchIndex,
+    store::{named_in, BatchMode, DishPatch, DishStore, PhotoStore, WriteCondition},
+};
+use crate::graphql::{
+    Dish, DishFilter, DishInput, DishOrder, DishPatchInput, Ingredient, Page, Photo, Quantity,
+    RawRecipeIngredient, RecipeIngredientInput,
+};
 
 pub(crate) struct DishLoader {
-    db_client: DynamoDbClient,
-    s3_client: S3Client,
+    store: Arc<dyn DishStore>,
+    photos: Arc<dyn PhotoStore>,
+    index: Arc<SearchIndex>,
 }
 
 #[async_trait::async_trait]
@@ -54,108 +26,340 @@ impl Loader<String> for DishLoader {
     type Error = Arc<Error>;
 
     async fn load(&self, keys: &[String]) -> Result<HashMap<String, Dish>, Self::Error> {
-        let keys = KeysAndAttributes::builder()
-            .set_keys(Some(
-                keys.iter()
-                    .map(|k| {
-                        let mut map = HashMap::new();
-                        map.insert("name".to_string(), AttributeValue::S(k.to_owned()));
-                        map
-                    })
-                    .collect(),
-            ))
-            .build();
-        let output = self
-            .db_client
-            .batch_get_item()
-            .request_items(TABLE_NAME, keys)
-            .send()
-            .await
-            .map_err(|e| Arc::new(e.into()))?;
-        match output.responses {
-            Some(mut items) => {
-                let items = items.remove(TABLE_NAME).expect("responses");
-                let dishes: Vec<Dish> = from_items(items).map_err(|e| Arc::new(e.into()))?;
-                Ok(dishes
-                    .into_iter()
-                    .map(|item| (item.name.clone(), item))
-                    .collect())
-            }
-            None => Ok(HashMap::default()),
-        }
-
-        // TODO process unprocessed keys
+        let dishes = self.store.get_many(keys).await.map_err(Arc::new)?;
+        Ok(dishes
+            .into_iter()
+            .map(|item| (item.name.clone(), item))
+            .collect())
     }
 }
 
 impl DishLoader {
-    pub(crate) fn new(dynamodb: DynamoDbClient, s3: S3Client) -> DishLoader {
+    pub(crate) fn new(
+        store: Arc<dyn DishStore>,
+        photos: Arc<dyn PhotoStore>,
+        index: Arc<SearchIndex>,
+    ) -> DishLoader {
         DishLoader {
-            db_client: dynamodb,
-            s3_client: s3,
+            store,
+            photos,
+            index,
         }
     }
-    pub(crate) async fn load_all(&self) -> Result<Vec<Dish>, Error> {
-        let output = self.db_client.scan().table_name(TABLE_NAME).send().await?;
-        match output.items {
-            Some(items) => Ok(from_items(items)?),
-            None => Ok(vec![]),
+    /// Without `order` dishes come in the order the store scans them.
+    pub(crate) async fn load_all(
+        &self,
+        filter: Option<&DishFilter>,
+        order: Option<&DishOrder>,
+    ) -> Result<Vec<Dish>, Error> {
+        let mut dishes = self.store.scan(filter).await?;
+        if let Some(order) = order {
+            dishes.sort_by(|a, b| order.compare(a, b));
         }
+        Ok(dishes)
     }
-    pub(crate) async fn update_many(
+    pub(crate) async fn load_page(
+        &self,
+        filter: Option<&DishFilter>,
+        order: Option<&DishOrder>,
+        after: Option<String>,
+        limit: usize,
+    ) -> Result<Page<Dish>, Error> {
+        match order {
+            Some(_) => Ok(self
+                .load_window(filter, order, after, None, Some(limit), None)
+                .await?
+                .into_page(|item| &item.name)),
+            None => self.store.scan_page(filter, after, Some(limit)).await,
+        }
+    }
+    /// Sorting needs every matching dish, only unsorted windows are read
+    /// page by page.
+    pub(crate) async fn load_window(
+        &self,
+        filter: Option<&DishFilter>,
+        order: Option<&DishOrder>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<usize>,
+        last: Option<usize>,
+    ) -> Result<Window<Dish>, Error> {
+        match order {
+            Some(_) => slice(
+                self.load_all(filter, order).await?,
+                |item| &item.name,
+                after,
+                before,
+                first,
+                last,
+            ),
+            None => {
+                walk(
+                    |after, limit| self.store.scan_page(filter, after, limit),
+                    |item| &item.name,
+                    after,
+                    before,
+                    first,
+                    last,
+                )
+                .await
+            }
+        }
+    }
+    pub(crate) async fn count(&self, filter: Option<&DishFilter>) -> Result<usize, Error> {
+        self.store.count(filter).await
+    }
+    /// Writes dishes when `condition` holds, as `mode` says, along with
+    /// the ones of `new_ingredients` they name. Returns whether it held for
+    /// each dish, in order. Photos of dishes not written are deleted again,
+    /// as are all of them when the write fails.
+    pub(crate) async fn write_many(
         &self,
         ctx: &Context<'_>,
         items: &[DishInput],
-    ) -> Result<(), Error> {
-        for item in items {
-            let mut update_item = self
-                .db_client
-                .update_item()
-                .table_name(TABLE_NAME)
-                .key("name", AttributeValue::S(item.name.clone()))
-                .update_expression(UPDATE_EXP);
-            if let Some(photo) = &item.photo {
-                let upload_value = photo.value(ctx)?;
-                let key = format!("{}.jpg", nanoid!());
-                self.s3_client
-                    .put_object()
-                    .bucket(S3_BUCKET)
-                    .key(key.clone())
-                    .content_type(
-                        upload_value
-                            .content_type
-                            .unwrap_or_else(|| "image/jpeg".to_string()),
-                    )
-                    .body(ByteStream::from_file(File::from_std(upload_value.content)).await?)
-                    .send()
-                    .await?;
-                update_item = update_item
-                    .set_update_expression(Some(UPDATE_EXP_WITH_PHOTO.to_string()))
-                    .expression_attribute_values(
-                        ":photo",
-                        to_attribute_value(Photo {
-                            filename: Some(key),
-                            mimetype: None,
-                            encoding: None,
-                        })?,
-                    );
+        condition: WriteCondition,
+        mode: BatchMode,
+        new_ingredients: &[Ingredient],
+    ) -> Result<Vec<bool>, Error> {
+        let uploads = items.iter().map(|item| item.photo.as_ref());
+        let photos = self.upload_photos(ctx, uploads.collect()).await?;
+        let mut dishes = Vec::with_capacity(items.len());
+        for (item, photo) in items.iter().zip(photos) {
+            dishes.push(Dish {
+                name: item.name.clone(),
+                meal: item.meal,
+                ingredients: recipe(&item.ingredients),
+                spicy: item.spicy,
+                cook_time: item.cook_time,
+                recipe_link: item.recipe_link.clone(),
+                serving: item.serving,
+                one_dish: item.one_dish,
+                soup: item.soup,
+                style: item.style.clone(),
+                photo,
+                suppressed: item.suppressed,
+            });
+        }
+        let result = self
+            .store
+            .update_many_if(&dishes, condition, mode, new_ingredients)
+            .await;
+        let mut updated = vec![];
+        let mut unused = vec![];
+        for (dish, written) in dishes.into_iter().zip(mode.written(&result, items.len())) {
+            if written {
+                updated.push(dish);
+            } else if let Some(photo) = dish.photo {
+                unused.push(photo);
+            }
+        }
+        let recipes = updated.iter().map(|dish| dish.ingredients.as_slice());
+        self.index
+            .update_ingredients(&named_in(new_ingredients, recipes));
+        self.index.update_dishes(&updated);
+        self.delete_unused(result, unused.iter().collect()).await
+    }
+
+    /// Applies each patch to its dish, as `mode` says when some dishes do
+    /// not exist, creating the ones of `new_ingredients` they name. Returns
+    /// whether each dish existed, in order. Photos replaced or cleared by a
+    /// patch are deleted, as are photos uploaded for dishes not patched or
+    /// for a write that fails.
+    pub(crate) async fn patch_many(
+        &self,
+        ctx: &Context<'_>,
+        items: &[DishPatchInput],
+        mode: BatchMode,
+        new_ingredients: &[Ingredient],
+    ) -> Result<Vec<bool>, Error> {
+        let names: Vec<String> = items.iter().map(|item| item.name.clone()).collect();
+        let old: HashMap<String, Dish> = self
+            .store
+            .get_many(&names)
+            .await?
+            .into_iter()
+            .map(|dish| (dish.name.clone(), dish))
+            .collect();
+        let uploads = items.iter().map(|item| item.photo.as_opt_ref().flatten());
+        let photos = self.upload_photos(ctx, uploads.collect()).await?;
+        let mut patches = Vec::with_capacity(items.len());
+        for (item, uploaded) in items.iter().zip(photos) {
+            let photo = match &item.photo {
+                MaybeUndefined::Value(_) => Some(uploaded),
+                MaybeUndefined::Null => Some(None),
+                MaybeUndefined::Undefined => None,
+            };
+            patches.push(DishPatch {
+                name: item.name.clone(),
+                meal: item.meal.into(),
+                ingredients: item
+                    .ingredients
+                    .as_opt_ref()
+                    .map(|ingredients| ingredients.map(|i| recipe(i)).unwrap_or_default()),
+                spicy: item.spicy.into(),
+                cook_time: item.cook_time.into(),
+                recipe_link: item.recipe_link.clone().into(),
+                serving: item.serving.into(),
+                one_dish: item.one_dish.into(),
+                soup: item.soup.into(),
+                style: item.style.clone().into(),
+                photo,
+                suppressed: item.suppressed.into(),
+            });
+        }
+        let result = self.store.patch_many(&patches, mode, new_ingredients).await;
+        let written = mode.written(&result, items.len());
+        let mut updated = vec![];
+        let mut unused = vec![];
+        for (patch, patched) in patches.iter().zip(written) {
+            if patched {
+                updated.push(patch.name.clone());
             }
-            update_item
-                .expression_attribute_names("#st", "style")
-                .expression_attribute_values(":meal", to_attribute_value(&item.meal)?)
-                .expression_attribute_values(":ingredients", to_attribute_value(&item.ingredients)?)
-                .expression_attribute_values(":spicy", to_attribute_value(&item.spicy)?)
-                .expression_attribute_values(":cook_time", to_attribute_value(&item.cook_time)?)
-                .expression_attribute_values(":recipe_link", to_attribute_value(&item.recipe_link)?)
-                .expression_attribute_values(":serving", to_attribute_value(&item.serving)?)
-                .expression_attribute_values(":one_dish", to_attribute_value(&item.one_dish)?)
-                .expression_attribute_values(":soup", to_attribute_value(&item.soup)?)
-                .expression_attribute_values(":style", to_attribute_value(&item.style)?)
-                .expression_attribute_values(":suppressed", to_attribute_value(&item.suppressed)?)
-                .send()
-                .await?;
+            let Some(photo) = &patch.photo else {
+                continue;
+            };
+            let replaced = match patched {
+                true => old.get(&patch.name).and_then(|dish| dish.photo.as_ref()),
+                false => photo.as_ref(),
+            };
+            unused.extend(replaced);
+        }
+        let dishes = self.store.get_many(&updated).await?;
+        let recipes = dishes.iter().map(|dish| dish.ingredients.as_slice());
+        self.index
+            .update_ingredients(&named_in(new_ingredients, recipes));
+        self.index.update_dishes(&dishes);
+        self.delete_unused(result, unused).await
+    }
+    /// Uploads each of `uploads` that is set. When one fails, those uploaded
+    /// already are deleted again.
+    async fn upload_photos(
+        &self,
+        ctx: &Context<'_>,
+        uploads: Vec<Option<&Upload>>,
+    ) -> Result<Vec<Option<Photo>>, Error> {
+        let mut photos = Vec::with_capacity(uploads.len());
+        for upload in uploads {
+            let photo = match upload {
+                Some(upload) => match self.upload_photo(ctx, upload).await {
+                    Ok(photo) => Some(photo),
+                    Err(err) => {
+                        // The upload error is the one reported
+                        let _ = self.delete_photos(photos.iter().flatten().collect()).await;
+                        return Err(err);
+                    }
+                },
+                None => None,
+            };
+            photos.push(photo);
         }
-        // TODO handle error
+        Ok(photos)
+    }
+    /// Deletes the stored files of `photos`, all of them even when one
+    /// fails, whose error is returned.
+    async fn delete_photos(&self, photos: Vec<&Photo>) -> Result<(), Error> {
+        let mut result = Ok(());
+        for photo in photos {
+            if let Some(key) = &photo.filename {
+                let deleted = self.photos.delete(key).await;
+                result = result.and(deleted);
+            }
+        }
+        result
+    }
+    /// Deletes the photos a write left unused and passes its `result` on.
+    /// When the write failed, its error is the one reported rather than a
+    /// failure to delete them.
+    async fn delete_unused(
+        &self,
+        result: Result<Vec<bool>, Error>,
+        unused: Vec<&Photo>,
+    ) -> Result<Vec<bool>, Error> {
+        let deleted = self.delete_photos(unused).await;
+        let held = result?;
+        deleted?;
+        Ok(held)
+    }
+    async fn upload_photo(&self, ctx: &Context<'_>, photo: &Upload) -> Result<Photo, Error> {
+        let upload_value = photo.value(ctx)?;
+        let key = format!("{}.jpg", nanoid!());
+        self.photos
+            .put(
+                &key,
+                upload_value.content_type.as_deref().unwrap_or("image/jpeg"),
+                upload_value.content,
+            )
+            .await?;
+        Ok(Photo {
+            filename: Some(key),
+            mimetype: None,
+            encoding: None,
+        })
+    }
+    /// Dishes whose recipe names any of `ingredients`.
+    pub(crate) async fn load_using(&self, ingredients: &[String]) -> Result<Vec<Dish>, Error> {
+        let filter = DishFilter {
+            or: Some(
+                ingredients
+                    .iter()
+                    .map(|name| DishFilter {
+                        ingredient: Some(name.clone()),
+                        ..DishFilter::default()
+                    })
+                    .collect(),
+            ),
+            ..DishFilter::default()
+        };
+        self.store.scan(Some(&filter)).await
+    }
+    /// Deletes the dishes that exist in `names` along with their photos.
+    /// Returns the names of the dishes deleted. Photos are deleted for
+    /// every dish that was, even when the store fails part way, and a photo
+    /// that cannot be deleted is left behind.
+    pub(crate) async fn delete_many(&self, names: &[String]) -> Result<Vec<String>, Error> {
+        let dishes = self.store.get_many(names).await?;
+        let names: Vec<String> = dishes.iter().map(|dish| dish.name.clone()).collect();
+        let result = self.store.delete_many(&names).await;
+        // Read as a best effort write, which also tells a batch cut short
+        let held = result.map(|()| vec![true; names.len()]);
+        let written = BatchMode::BestEffort.written(&held, names.len());
+        let mut deleted = vec![];
+        let mut photos = vec![];
+        for (dish, written) in dishes.iter().zip(written) {
+            if written {
+                deleted.push(dish.name.clone());
+                photos.extend(&dish.photo);
+            }
+        }
+        self.index.remove_dishes(&deleted);
+        // The dishes are gone either way, a leftover photo is only clutter
+        let _ = self.delete_photos(photos).await;
+        held.map(|_| deleted)
+    }
+
+    /// Renames dish `from` to `to`, moving its cook log and meal plan
+    /// references along with it.
+    pub(crate) async fn rename(&self, from: &str, to: &str) -> Result<(), Error> {
+        self.store.rename(from, to).await?;
+        self.index.remove_dishes(&[from.to_string()]);
+        let renamed = self.store.get_many(&[to.to_string()]).await?;
+        self.index.update_dishes(&renamed);
         Ok(())
     }
+
+    /// Stores every recipe kept as text parsed. Returns the number of
+    /// dishes rewritten.
+    pub(crate) async fn migrate_quantities(&self) -> Result<usize, Error> {
+        self.store.migrate_quantities().await
+    }
+}
+
+fn recipe(ingredients: &[RecipeIngredientInput]) -> Vec<RawRecipeIngredient> {
+    ingredients
+        .iter()
+        .map(|i| RawRecipeIngredient {
+            name: i.name.clone(),
+            quantity: Quantity::parse(&i.quantity),
+        })
+        .collect()
 }
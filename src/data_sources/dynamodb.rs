@@ -0,0 +1,623 @@
+pub(crate) mod cook_log;
+pub(crate) mod dishes;
+pub(crate) mod ingredients;
+pub(crate) mod meal_plans;
+pub(crate) mod pantry;
+pub(crate) mod shares;
+pub(crate) mod users;
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Error};
+use aws_sdk_dynamodb::{
+    error::TransactWriteItemsErrorKind,
+    model::{AttributeValue, Delete, KeysAndAttributes, Put, Select, TransactWriteItem},
+    types::SdkError,
+    Client,
+};
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use serde_dynamo::from_items;
+
+use super::store::{BatchMode, PartialBatch, WriteCondition};
+
+/// DynamoDB implementation of every store in `data_sources::store`.
+pub(crate) struct DynamoDbStore {
+    db_client: Client,
+}
+
+impl DynamoDbStore {
+    pub(crate) fn new(dynamodb: Client) -> DynamoDbStore {
+        DynamoDbStore {
+            db_client: dynamodb,
+        }
+    }
+}
+
+/// Turns the `LastEvaluatedKey` of a table keyed by `name` into a cursor.
+fn last_evaluated_name(key: Option<HashMap<String, AttributeValue>>) -> Option<String> {
+    match key?.remove("name") {
+        Some(AttributeValue::S(name)) => Some(name),
+        _ => None,
+    }
+}
+
+/// Condition expression of a conditional write to a table keyed by `name`.
+/// `name` is a reserved word, the expression refers to it as `#key`.
+fn key_condition(condition: WriteCondition) -> &'static str {
+    match condition {
+        WriteCondition::Absent => "attribute_not_exists(#key)",
+        WriteCondition::Present => "attribute_exists(#key)",
+    }
+}
+
+/// Key or item of a table, attribute by attribute.
+type Attributes = HashMap<String, AttributeValue>;
+
+/// Most keys one `BatchGetItem` call accepts.
+const MAX_BATCH_GET_KEYS: usize = 100;
+
+/// Calls made for one chunk of keys before giving up on those DynamoDB
+/// keeps leaving unprocessed.
+const MAX_BATCH_GET_ATTEMPTS: u32 = 5;
+
+/// Longest wait before the first retry, doubled for every retry after it.
+const BATCH_GET_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Keys of a table whose key is the single string attribute `attribute`.
+/// Repeated names are looked up once, `BatchGetItem` rejects duplicates.
+fn string_keys(attribute: &str, names: &[String]) -> Vec<Attributes> {
+    let mut seen = HashSet::new();
+    names
+        .iter()
+        .filter(|name| seen.insert(name.as_str()))
+        .map(|name| {
+            let mut map = HashMap::new();
+            map.insert(attribute.to_string(), AttributeValue::S(name.clone()));
+            map
+        })
+        .collect()
+}
+
+/// Wait before retry number `retry`, a random fraction of the exponential
+/// backoff so that throttled callers do not retry in lockstep.
+fn backoff(retry: u32) -> Duration {
+    let cap = BATCH_GET_BACKOFF * 2u32.pow(retry - 1);
+    cap.mul_f64(rand::thread_rng().gen())
+}
+
+/// The DynamoDB calls the batch and transaction helpers below make, so
+/// that tests can stand in for the client.
+#[async_trait::async_trait]
+trait DynamoDbCalls: Sync {
+    /// Reads `keys` of `table_name` with one `BatchGetItem` call. Returns the
+    /// items found and the keys left unprocessed.
+    async fn batch_get_item(
+        &self,
+        table_name: &str,
+        keys: Vec<Attributes>,
+    ) -> Result<(Vec<Attributes>, Vec<Attributes>), Error>;
+    /// Sends `actions` in one `TransactWriteItems` call. Returns whether the
+    /// condition of each action held, nothing is written unless all of
+    /// them did.
+    async fn transact_write_items(
+        &self,
+        actions: Vec<TransactWriteItem>,
+    ) -> Result<Vec<bool>, Error>;
+}
+
+#[async_trait::async_trait]
+impl DynamoDbCalls for Client {
+    async fn batch_get_item(
+        &self,
+        table_name: &str,
+        keys: Vec<Attributes>,
+    ) -> Result<(Vec<Attributes>, Vec<Attributes>), Error> {
+        let keys = KeysAndAttributes::builder().set_keys(Some(keys)).build();
+        let output = self
+            .batch_get_item()
+            .request_items(table_name, keys)
+            .send()
+            .await?;
+        let items = output
+            .responses
+            .and_then(|mut responses| responses.remove(table_name))
+            .unwrap_or_default();
+        let unprocessed = output
+            .unprocessed_keys
+            .and_then(|mut unprocessed| unprocessed.remove(table_name))
+            .and_then(|unprocessed| unprocessed.keys)
+            .unwrap_or_default();
+        Ok((items, unprocessed))
+    }
+    async fn transact_write_items(
+        &self,
+        actions: Vec<TransactWriteItem>,
+    ) -> Result<Vec<bool>, Error> {
+        let count = actions.len();
+        let result = self
+            .transact_write_items()
+            .set_transact_items(Some(actions))
+            .send()
+            .await;
+        let err = match result {
+            Ok(_) => return Ok(vec![true; count]),
+            Err(SdkError::ServiceError { err, .. }) => err,
+            Err(err) => return Err(err.into()),
+        };
+        let TransactWriteItemsErrorKind::TransactionCanceledException(canceled) = &err.kind else {
+            return Err(err.into());
+        };
+        let reasons = canceled.cancellation_reasons().unwrap_or_default();
+        if reasons.len() != count {
+            return Err(err.into());
+        }
+        // Every action gets a reason, `None` for those that would have succeeded
+        reasons
+            .iter()
+            .map(|reason| match reason.code() {
+                None | Some("None") => Ok(true),
+                Some("ConditionalCheckFailed") => Ok(false),
+                Some(code) => Err(anyhow!(
+                    "transaction canceled, {}: {}",
+                    code,
+                    reason.message().unwrap_or_default()
+                )),
+            })
+            .collect()
+    }
+}
+
+/// Reads the items under `keys` with `BatchGetItem`, `MAX_BATCH_GET_KEYS`
+/// at a time. Keys DynamoDB leaves unprocessed are asked for again after a
+/// backoff, failing when some remain after `MAX_BATCH_GET_ATTEMPTS` calls.
+/// Keys without an item are left out.
+async fn batch_get<T: DeserializeOwned>(
+    db_client: &impl DynamoDbCalls,
+    table_name: &str,
+    keys: Vec<Attributes>,
+) -> Result<Vec<T>, Error> {
+    let mut items = vec![];
+    for chunk in keys.chunks(MAX_BATCH_GET_KEYS) {
+        let mut pending = chunk.to_vec();
+        let mut attempts = 0;
+        while !pending.is_empty() {
+            if attempts == MAX_BATCH_GET_ATTEMPTS {
+                return Err(anyhow!(
+                    "{} keys of {} still unprocessed after {} attempts",
+                    pending.len(),
+                    table_name,
+                    attempts
+                ));
+            }
+            if attempts > 0 {
+                tokio::time::sleep(backoff(attempts)).await;
+            }
+            attempts += 1;
+            let (found, unprocessed) = db_client.batch_get_item(table_name, pending).await?;
+            items.append(&mut from_items(found)?);
+            pending = unprocessed;
+        }
+    }
+    Ok(items)
+}
+
+/// Most actions one `TransactWriteItems` call accepts.
+const MAX_TRANSACT_ITEMS: usize = 100;
+
+/// Reads one item as stored, `None` when there is none under `key`.
+async fn get_raw(
+    db_client: &Client,
+    table_name: &str,
+    key: (&str, &str),
+) -> Result<Option<Attributes>, Error> {
+    let output = db_client
+        .get_item()
+        .table_name(table_name)
+        .key(key.0, AttributeValue::S(key.1.to_string()))
+        .consistent_read(true)
+        .send()
+        .await?;
+    Ok(output.item)
+}
+
+/// Actions moving `item` to the key whose first attribute is `to`, the
+/// others being left as they are. The put fails when that key is taken and
+/// the delete when the item is gone or any of its `attributes` changed
+/// since it was read, so the transaction holding them is canceled instead
+/// of losing either or writing back a stale copy.
+fn move_item(
+    table_name: &str,
+    key: &[&str],
+    attributes: &[&str],
+    mut item: Attributes,
+    to: &str,
+) -> Result<[TransactWriteItem; 2], Error> {
+    let mut delete = Delete::builder()
+        .table_name(table_name)
+        .expression_attribute_names("#key", key[0]);
+    for attribute in key {
+        let value = item
+            .get(*attribute)
+            .ok_or_else(|| anyhow!("item without {} in {}", attribute, table_name))?;
+        delete = delete.key(*attribute, value.clone());
+    }
+    let mut condition = vec!["attribute_exists(#key)".to_string()];
+    for (i, attribute) in attributes.iter().enumerate() {
+        let name = format!("#a{}", i);
+        delete = delete.expression_attribute_names(&name, *attribute);
+        match item.get(*attribute) {
+            Some(value) => {
+                let placeholder = format!(":a{}", i);
+                condition.push(format!("{} = {}", name, placeholder));
+                delete = delete.expression_attribute_values(placeholder, value.clone());
+            }
+            None => condition.push(format!("attribute_not_exists({})", name)),
+        }
+    }
+    let delete = delete.condition_expression(condition.join(" AND ")).build();
+    item.insert(key[0].to_string(), AttributeValue::S(to.to_string()));
+    let put = Put::builder()
+        .table_name(table_name)
+        .set_item(Some(item))
+        .condition_expression("attribute_not_exists(#key)")
+        .expression_attribute_names("#key", key[0])
+        .build();
+    Ok([
+        TransactWriteItem::builder().put(put).build(),
+        TransactWriteItem::builder().delete(delete).build(),
+    ])
+}
+
+/// Fails when renaming `kind` `from` takes more writes than one transaction
+/// holds. A rename is written all at once or not at all.
+fn check_rename_size(kind: &str, from: &str, writes: usize) -> Result<(), Error> {
+    if writes > MAX_TRANSACT_ITEMS {
+        return Err(anyhow!(
+            "renaming {} {} takes {} writes, more than the {} of one transaction",
+            kind,
+            from,
+            writes,
+            MAX_TRANSACT_ITEMS
+        ));
+    }
+    Ok(())
+}
+
+/// Writes all of `actions` or none of them. Returns whether the condition
+/// of each action held, nothing is written unless all of them did.
+async fn transact(
+    db_client: &impl DynamoDbCalls,
+    actions: Vec<TransactWriteItem>,
+) -> Result<Vec<bool>, Error> {
+    let count = actions.len();
+    if count > MAX_TRANSACT_ITEMS {
+        return Err(anyhow!(
+            "{} writes do not fit in one transaction of at most {}",
+            count,
+            MAX_TRANSACT_ITEMS
+        ));
+    }
+    if count == 0 {
+        return Ok(vec![]);
+    }
+    db_client.transact_write_items(actions).await
+}
+
+/// Writes each of `actions` whose condition holds, in transactions of at
+/// most `MAX_TRANSACT_ITEMS`. A transaction canceled by failed conditions
+/// is retried without the actions whose condition failed. Returns whether
+/// the condition of each action held. Failing after some transactions went
+/// through gives a `PartialBatch` telling which.
+async fn transact_each(
+    db_client: &impl DynamoDbCalls,
+    actions: Vec<TransactWriteItem>,
+) -> Result<Vec<bool>, Error> {
+    let mut held = vec![true; actions.len()];
+    for (chunk, actions) in actions.chunks(MAX_TRANSACT_ITEMS).enumerate() {
+        let offset = chunk * MAX_TRANSACT_ITEMS;
+        let mut pending: Vec<usize> = (0..actions.len()).collect();
+        while !pending.is_empty() {
+            let attempt = pending.iter().map(|i| actions[*i].clone()).collect();
+            let results = match transact(db_client, attempt).await {
+                Ok(results) => results,
+                Err(error) if offset == 0 => return Err(error),
+                Err(error) => {
+                    let held = held
+                        .iter()
+                        .enumerate()
+                        .map(|(i, held)| (i < offset || !held).then_some(*held))
+                        .collect();
+                    return Err(PartialBatch { held, error }.into());
+                }
+            };
+            if results.iter().all(|held| *held) {
+                break;
+            }
+            let mut retry = vec![];
+            for (i, result) in pending.into_iter().zip(results) {
+                match result {
+                    true => retry.push(i),
+                    false => held[offset + i] = false,
+                }
+            }
+            pending = retry;
+        }
+    }
+    Ok(held)
+}
+
+/// Writes `actions` the way `mode` says. Returns whether the condition of
+/// each action held.
+async fn transact_batch(
+    db_client: &impl DynamoDbCalls,
+    actions: Vec<TransactWriteItem>,
+    mode: BatchMode,
+) -> Result<Vec<bool>, Error> {
+    match mode {
+        BatchMode::Atomic => transact(db_client, actions).await,
+        BatchMode::BestEffort => transact_each(db_client, actions).await,
+    }
+}
+
+/// Packs `groups` of actions, in order, into transactions of at most
+/// `MAX_TRANSACT_ITEMS` without splitting any group.
+fn pack_groups(groups: Vec<Vec<TransactWriteItem>>) -> Vec<Vec<TransactWriteItem>> {
+    let mut transactions: Vec<Vec<TransactWriteItem>> = vec![];
+    for mut group in groups {
+        match transactions.last_mut() {
+            Some(current) if current.len() + group.len() <= MAX_TRANSACT_ITEMS => {
+                current.append(&mut group)
+            }
+            _ => transactions.push(group),
+        }
+    }
+    transactions
+}
+
+/// Writes `groups` of actions in order, in as few transactions as
+/// `pack_groups` makes, so they are written together when they all fit.
+/// Stops at the first transaction whose conditions fail, which leaves the
+/// ones before it written, and returns whether every condition held.
+async fn transact_groups(
+    db_client: &impl DynamoDbCalls,
+    groups: Vec<Vec<TransactWriteItem>>,
+) -> Result<bool, Error> {
+    for actions in pack_groups(groups) {
+        if !transact(db_client, actions).await?.iter().all(|held| *held) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Counts the items of a table with `Select::Count` scans.
+async fn count(db_client: &Client, table_name: &str) -> Result<usize, Error> {
+    let mut count = 0;
+    let mut start_key = None;
+    loop {
+        let output = db_client
+            .scan()
+            .table_name(table_name)
+            .select(Select::Count)
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
+        count += output.count as usize;
+        start_key = output.last_evaluated_key;
+        if start_key.is_none() {
+            return Ok(count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Named {
+        name: String,
+    }
+
+    /// Stands in for DynamoDB. Serves `BatchGetItem` from `items`,
+    /// processing at most `per_call` keys of each call. Transactions fail
+    /// the conditions of the actions on `conflicts`, and call number
+    /// `failing_call` fails outright.
+    #[derive(Default)]
+    pub(super) struct FakeDb {
+        pub items: Vec<String>,
+        pub per_call: usize,
+        pub conflicts: Vec<String>,
+        pub failing_call: Option<usize>,
+        /// Number of keys each `BatchGetItem` call asked for
+        pub batch_gets: Mutex<Vec<usize>>,
+        /// Key names of the actions of each `TransactWriteItems` call
+        pub transactions: Mutex<Vec<Vec<String>>>,
+    }
+
+    impl FakeDb {
+        fn table(count: usize, per_call: usize) -> FakeDb {
+            FakeDb {
+                items: (0..count).map(|i| i.to_string()).collect(),
+                per_call,
+                ..FakeDb::default()
+            }
+        }
+        fn batch_gets(&self) -> Vec<usize> {
+            self.batch_gets.lock().unwrap().clone()
+        }
+        pub(super) fn transactions(&self) -> Vec<Vec<String>> {
+            self.transactions.lock().unwrap().clone()
+        }
+    }
+
+    /// Name under which `action` writes, for the updates the stores make.
+    fn action_name(action: &TransactWriteItem) -> String {
+        let key = action.update().and_then(|update| update.key());
+        match key.and_then(|key| key.get("name")) {
+            Some(AttributeValue::S(name)) => name.clone(),
+            _ => panic!("unexpected action {:?}", action),
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl DynamoDbCalls for FakeDb {
+        async fn batch_get_item(
+            &self,
+            _table_name: &str,
+            mut keys: Vec<Attributes>,
+        ) -> Result<(Vec<Attributes>, Vec<Attributes>), Error> {
+            assert!(keys.len() <= MAX_BATCH_GET_KEYS);
+            self.batch_gets.lock().unwrap().push(keys.len());
+            let unprocessed = keys.split_off(self.per_call.min(keys.len()));
+            let found = keys
+                .into_iter()
+                .filter(|key| match &key["name"] {
+                    AttributeValue::S(name) => self.items.contains(name),
+                    _ => false,
+                })
+                .collect();
+            Ok((found, unprocessed))
+        }
+        async fn transact_write_items(
+            &self,
+            actions: Vec<TransactWriteItem>,
+        ) -> Result<Vec<bool>, Error> {
+            assert!(actions.len() <= MAX_TRANSACT_ITEMS);
+            let names: Vec<String> = actions.iter().map(action_name).collect();
+            let mut transactions = self.transactions.lock().unwrap();
+            transactions.push(names.clone());
+            if self.failing_call == Some(transactions.len() - 1) {
+                return Err(anyhow!("throttled"));
+            }
+            Ok(names
+                .iter()
+                .map(|name| !self.conflicts.contains(name))
+                .collect())
+        }
+    }
+
+    fn numbers(range: std::ops::Range<usize>) -> Vec<String> {
+        range.map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn string_keys_look_up_repeated_names_once() {
+        let names = ["b", "a", "b", "c", "a"].map(String::from);
+        let keys: Vec<_> = string_keys("name", &names)
+            .into_iter()
+            .map(|mut key| key.remove("name").unwrap())
+            .collect();
+        assert_eq!(
+            keys,
+            ["b", "a", "c"].map(|name| AttributeValue::S(name.into()))
+        );
+    }
+
+    #[test]
+    fn backoff_stays_under_its_doubling_cap() {
+        for retry in 1..MAX_BATCH_GET_ATTEMPTS {
+            let cap = BATCH_GET_BACKOFF * 2u32.pow(retry - 1);
+            for _ in 0..100 {
+                assert!(backoff(retry) <= cap);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_get_asks_for_keys_in_chunks() {
+        let table = FakeDb::table(200, MAX_BATCH_GET_KEYS);
+        let keys = string_keys("name", &numbers(50..300));
+        let items: Vec<Named> = batch_get(&table, "table", keys).await.unwrap();
+        assert_eq!(table.batch_gets(), [100, 100, 50]);
+        // Keys without an item are left out
+        let names: Vec<_> = items.into_iter().map(|item| item.name).collect();
+        assert_eq!(names, numbers(50..200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batch_get_asks_again_for_unprocessed_keys() {
+        let table = FakeDb::table(100, 40);
+        let keys = string_keys("name", &numbers(0..100));
+        let items: Vec<Named> = batch_get(&table, "table", keys).await.unwrap();
+        assert_eq!(table.batch_gets(), [100, 60, 20]);
+        assert_eq!(items.len(), 100);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batch_get_gives_up_on_keys_left_unprocessed() {
+        let table = FakeDb::table(10, 0);
+        let keys = string_keys("name", &numbers(0..10));
+        let result: Result<Vec<Named>, _> = batch_get(&table, "table", keys).await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "10 keys of table still unprocessed after 5 attempts"
+        );
+        assert_eq!(table.batch_gets(), [10; MAX_BATCH_GET_ATTEMPTS as usize]);
+    }
+
+    fn groups(sizes: &[usize]) -> Vec<Vec<TransactWriteItem>> {
+        sizes
+            .iter()
+            .map(|size| vec![TransactWriteItem::builder().build(); *size])
+            .collect()
+    }
+
+    fn packed(sizes: &[usize]) -> Vec<usize> {
+        pack_groups(groups(sizes)).iter().map(Vec::len).collect()
+    }
+
+    #[test]
+    fn groups_that_fit_share_one_transaction() {
+        assert_eq!(packed(&[2, 2, 1, 2]), [7]);
+        assert_eq!(packed(&[]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn groups_are_never_split() {
+        assert_eq!(packed(&[2; 51]), [100, 2]);
+        assert_eq!(packed(&[1, 99, 2]), [100, 2]);
+        assert_eq!(packed(&[99, 2, 1]), [99, 3]);
+    }
+
+    #[test]
+    fn moves_are_conditioned_on_the_item_read() {
+        let mut item = HashMap::new();
+        item.insert("name".to_string(), AttributeValue::S("a".into()));
+        item.insert("meal".to_string(), AttributeValue::S("dinner".into()));
+        let [put, delete] = move_item("dishes", &["name"], &["meal", "photo"], item, "b").unwrap();
+        let put = put.put().unwrap();
+        assert_eq!(put.item().unwrap()["name"], AttributeValue::S("b".into()));
+        assert_eq!(
+            put.condition_expression(),
+            Some("attribute_not_exists(#key)")
+        );
+        let delete = delete.delete().unwrap();
+        assert_eq!(delete.key().unwrap()["name"], AttributeValue::S("a".into()));
+        assert_eq!(
+            delete.condition_expression(),
+            Some("attribute_exists(#key) AND #a0 = :a0 AND attribute_not_exists(#a1)")
+        );
+        let values = delete.expression_attribute_values().unwrap();
+        assert_eq!(values[":a0"], AttributeValue::S("dinner".into()));
+        assert_eq!(values.len(), 1);
+    }
+
+    #[test]
+    fn renames_have_to_fit_in_one_transaction() {
+        assert!(check_rename_size("dish", "a", MAX_TRANSACT_ITEMS).is_ok());
+        let err = check_rename_size("dish", "a", MAX_TRANSACT_ITEMS + 1).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "renaming dish a takes 101 writes, more than the 100 of one transaction"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use anyhow::Error;
+use aws_sdk_dynamodb::{
+    model::{AttributeValue, TransactWriteItem},
+    types::SdkError,
+};
+use chrono::NaiveDate;
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use serde_dynamo::{from_items, to_attribute_value, to_item};
+
+use super::{move_item, DynamoDbStore};
+use crate::{
+    data_sources::store::CookLogStore,
+    graphql::{CookEntry, Meal},
+};
+
+const TABLE_NAME: &str = "todays-menu-cook-log";
+/// Global secondary index with partition key `date`.
+const DATE_INDEX: &str = "date-index";
+/// Ranges longer than this many days are scanned instead of queried by day.
+const MAX_INDEXED_DAYS: i64 = 62;
+/// Queries sent at once when reading several dishes or days.
+const MAX_PARALLEL_QUERIES: usize = 8;
+
+/// Stored entry. The sort key combines date and meal, so a dish is logged
+/// at most once per meal.
+#[derive(Serialize, Deserialize)]
+struct Item {
+    #[serde(flatten)]
+    entry: CookEntry,
+    cooked_at: String,
+}
+
+impl From<&CookEntry> for Item {
+    fn from(entry: &CookEntry) -> Self {
+        let meal = match entry.meal {
+            Meal::Lunch => "Lunch",
+            Meal::Dinner => "Dinner",
+        };
+        Item {
+            entry: entry.clone(),
+            cooked_at: format!("{}#{}", entry.date, meal),
+        }
+    }
+}
+
+impl DynamoDbStore {
+    /// All entries whose `key` is `value`, in the table or in `index`.
+    async fn query_cook_log(
+        &self,
+        index: Option<&str>,
+        key: &str,
+        value: &str,
+    ) -> Result<Vec<CookEntry>, Error> {
+        let mut entries = vec![];
+        let mut after = None;
+        loop {
+            let output = self
+                .db_client
+                .query()
+                .table_name(TABLE_NAME)
+                .set_index_name(index.map(str::to_string))
+                .key_condition_expression("#key = :value")
+                .expression_attribute_names("#key", key)
+                .expression_attribute_values(":value", AttributeValue::S(value.to_string()))
+                .set_exclusive_start_key(after)
+                .send()
+                .await?;
+            let items: Vec<Item> = from_items(output.items.unwrap_or_default())?;
+            entries.extend(items.into_iter().map(|item| item.entry));
+            match output.last_evaluated_key {
+                Some(key) => after = Some(key),
+                None => return Ok(entries),
+            }
+        }
+    }
+    /// Entries from `from` to `to` by reading the whole table, which beats
+    /// querying the date index one day at a time for long spans.
+    async fn scan_cook_log(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<CookEntry>, Error> {
+        let mut entries = vec![];
+        let mut after = None;
+        loop {
+            let output = self
+                .db_client
+                .scan()
+                .table_name(TABLE_NAME)
+                .filter_expression("#date BETWEEN :from AND :to")
+                .expression_attribute_names("#date", "date")
+                .expression_attribute_values(":from", to_attribute_value(from)?)
+                .expression_attribute_values(":to", to_attribute_value(to)?)
+                .set_exclusive_start_key(after)
+                .send()
+                .await?;
+            let items: Vec<Item> = from_items(output.items.unwrap_or_default())?;
+            entries.extend(items.into_iter().map(|item| item.entry));
+            match output.last_evaluated_key {
+                Some(key) => after = Some(key),
+                None => return Ok(entries),
+            }
+        }
+    }
+    /// Actions moving the entries of dish `from` to dish `to`, two for
+    /// each entry.
+    pub(super) async fn move_cook_log(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<TransactWriteItem>, Error> {
+        let mut actions = vec![];
+        let mut after = None;
+        loop {
+            let output = self
+                .db_client
+                .query()
+                .table_name(TABLE_NAME)
+                .key_condition_expression("dish_name = :dish_name")
+                .expression_attribute_values(":dish_name", AttributeValue::S(from.to_string()))
+                .consistent_read(true)
+                .set_exclusive_start_key(after)
+                .send()
+                .await?;
+            for item in output.items.unwrap_or_default() {
+                // Entries are never changed in place, only added and deleted
+                let keys = ["dish_name", "cooked_at"];
+                actions.extend(move_item(TABLE_NAME, &keys, &[], item, to)?);
+            }
+            match output.last_evaluated_key {
+                Some(key) => after = Some(key),
+                None => return Ok(actions),
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl CookLogStore for DynamoDbStore {
+    async fn get_for_dishes(&self, dish_names: &[String]) -> Result<Vec<CookEntry>, Error> {
+        let entries: Vec<Vec<CookEntry>> = stream::iter(dish_names.iter().cloned())
+            .map(
+                |dish_name| async move { self.query_cook_log(None, "dish_name", &dish_name).await },
+            )
+            .buffered(MAX_PARALLEL_QUERIES)
+            .try_collect()
+            .await?;
+        Ok(entries.concat())
+    }
+    async fn get_range(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<CookEntry>, Error> {
+        if (to - from).num_days() > MAX_INDEXED_DAYS {
+            return self.scan_cook_log(from, to).await;
+        }
+        let entries: Vec<Vec<CookEntry>> =
+            stream::iter(from.iter_days().take_while(|date| *date <= to))
+                .map(|date| async move {
+                    self.query_cook_log(Some(DATE_INDEX), "date", &date.to_string())
+                        .await
+                })
+                .buffered(MAX_PARALLEL_QUERIES)
+                .try_collect()
+                .await?;
+        Ok(entries.concat())
+    }
+    async fn put_many(&self, entries: &[CookEntry]) -> Result<Vec<CookEntry>, Error> {
+        let mut added = vec![];
+        for entry in entries {
+            let result = self
+                .db_client
+                .put_item()
+                .table_name(TABLE_NAME)
+                .set_item(Some(to_item(Item::from(entry))?))
+                .condition_expression("attribute_not_exists(dish_name)")
+                .send()
+                .await;
+            match result {
+                Ok(_) => added.push(entry.clone()),
+                Err(SdkError::ServiceError { err, .. })
+                    if err.is_conditional_check_failed_exception() => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(added)
+    }
+}
@@ -0,0 +1,793 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Error};
+use aws_sdk_dynamodb::model::{AttributeValue, ConditionCheck, Delete, TransactWriteItem, Update};
+use serde::Serialize;
+use serde_dynamo::{from_item, from_items, to_attribute_value};
+
+use super::{
+    batch_get, check_rename_size, count, get_raw, ingredients::ingredient_create, key_condition,
+    last_evaluated_name, move_item, string_keys, transact, transact_each, DynamoDbCalls,
+    DynamoDbStore,
+};
+use crate::{
+    data_sources::store::{
+        check_rename, named_in, BatchMode, DishPatch, DishStore, PartialBatch, WriteCondition,
+    },
+    graphql::{Dish, DishFilter, Ingredient, Page, RawRecipeIngredient},
+};
+
+const TABLE_NAME: &str = "todays-menu-dishes";
+
+/// Attributes of a dish besides its name.
+const ATTRIBUTES: &[&str] = &[
+    "meal",
+    "ingredients",
+    "spicy",
+    "cook_time",
+    "recipe_link",
+    "serving",
+    "one_dish",
+    "soup",
+    "style",
+    "photo",
+    "suppressed",
+];
+
+const UPDATE_EXP: &str = "SET
+    meal = :meal,
+    ingredients = :ingredients,
+    spicy = :spicy,
+    cook_time = :cook_time,
+    recipe_link = :recipe_link,
+    serving = :serving,
+    one_dish = :one_dish,
+    soup = :soup,
+    #st = :style,
+    suppressed = :suppressed
+";
+
+const UPDATE_EXP_WITH_PHOTO: &str = "SET
+    meal = :meal,
+    ingredients = :ingredients,
+    spicy = :spicy,
+    cook_time = :cook_time,
+    recipe_link = :recipe_link,
+    serving = :serving,
+    one_dish = :one_dish,
+    soup = :soup,
+    #st = :style,
+    suppressed = :suppressed,
+    photo = :photo
+";
+
+/// Translates a `DishFilter` into a scan filter expression. Predicates
+/// DynamoDB cannot express are left out, which only ever widens the
+/// expression, so results still have to go through `DishFilter::matches`.
+#[derive(Default)]
+struct FilterExpression {
+    names: HashMap<String, String>,
+    values: HashMap<String, AttributeValue>,
+    /// Number of the next value placeholder
+    next_value: usize,
+}
+
+impl FilterExpression {
+    fn name(&mut self, attribute: &str) -> String {
+        let placeholder = format!("#{}", attribute);
+        self.names
+            .insert(placeholder.clone(), attribute.to_string());
+        placeholder
+    }
+    fn value(&mut self, value: impl Serialize) -> Result<String, Error> {
+        let placeholder = format!(":f{}", self.next_value);
+        self.next_value += 1;
+        self.values
+            .insert(placeholder.clone(), to_attribute_value(value)?);
+        Ok(placeholder)
+    }
+    fn compare(
+        &mut self,
+        attribute: &str,
+        op: &str,
+        value: impl Serialize,
+    ) -> Result<String, Error> {
+        Ok(format!(
+            "{} {} {}",
+            self.name(attribute),
+            op,
+            self.value(value)?
+        ))
+    }
+    /// Unset flags count as false, so `false` is matched by negating `true`.
+    fn flag(&mut self, attribute: &str, value: bool) -> Result<String, Error> {
+        let expression = self.compare(attribute, "=", true)?;
+        Ok(match value {
+            true => expression,
+            false => format!("NOT {}", expression),
+        })
+    }
+    /// `None` when nothing in the filter could be pushed down.
+    fn build(&mut self, filter: &DishFilter) -> Result<Option<String>, Error> {
+        let mut parts = vec![];
+        if let Some(meal) = filter.meal {
+            parts.push(self.compare("meal", "=", meal)?);
+        }
+        if let Some(soup) = filter.soup {
+            parts.push(self.flag("soup", soup)?);
+        }
+        if let Some(one_dish) = filter.one_dish {
+            parts.push(self.flag("one_dish", one_dish)?);
+        }
+        if let Some(style) = &filter.style {
+            parts.push(self.compare("style", "=", style)?);
+        }
+        if let Some(max_cook_time) = filter.max_cook_time {
+            parts.push(self.compare("cook_time", "<=", max_cook_time)?);
+        }
+        if let Some(min_spicy) = filter.min_spicy {
+            parts.push(self.compare("spicy", ">=", min_spicy)?);
+        }
+        if let Some(max_spicy) = filter.max_spicy {
+            parts.push(self.compare("spicy", "<=", max_spicy)?);
+        }
+        if let Some(suppressed) = filter.suppressed {
+            parts.push(self.flag("suppressed", suppressed)?);
+        }
+        // Recipe lines are maps, `contains` cannot match them by name alone,
+        // so `ingredient` is left to `DishFilter::matches`.
+        for filter in filter.and.iter().flatten() {
+            if let Some(expression) = self.build(filter)? {
+                parts.push(expression);
+            }
+        }
+        if let Some(filters) = &filter.or {
+            // Built aside, the names and values of an `or` that cannot be
+            // pushed down must not end up unused in the scan request
+            let mut scratch = FilterExpression {
+                next_value: self.next_value,
+                ..FilterExpression::default()
+            };
+            let mut alternatives = vec![];
+            for filter in filters {
+                match scratch.build(filter)? {
+                    Some(expression) => alternatives.push(expression),
+                    // One alternative matches anything, so does the whole `or`
+                    None => {
+                        alternatives.clear();
+                        break;
+                    }
+                }
+            }
+            if !alternatives.is_empty() {
+                parts.push(format!("({})", alternatives.join(" OR ")));
+                self.names.extend(scratch.names);
+                self.values.extend(scratch.values);
+                self.next_value = scratch.next_value;
+            }
+        }
+        Ok(match parts.is_empty() {
+            true => None,
+            false => Some(format!("({})", parts.join(" AND "))),
+        })
+    }
+}
+
+/// Builds the update expression of a `DishPatch`, setting the attributes it
+/// changes and removing those it clears.
+#[derive(Default)]
+struct UpdateExpression {
+    set: Vec<String>,
+    remove: Vec<String>,
+    names: HashMap<String, String>,
+    values: HashMap<String, AttributeValue>,
+}
+
+impl UpdateExpression {
+    fn change<T: Serialize>(
+        &mut self,
+        attribute: &str,
+        value: &Option<Option<T>>,
+    ) -> Result<(), Error> {
+        let Some(value) = value else {
+            return Ok(());
+        };
+        let name = format!("#{}", attribute);
+        self.names.insert(name.clone(), attribute.to_string());
+        match value {
+            Some(value) => {
+                let placeholder = format!(":{}", attribute);
+                self.values
+                    .insert(placeholder.clone(), to_attribute_value(value)?);
+                self.set.push(format!("{} = {}", name, placeholder));
+            }
+            None => self.remove.push(name),
+        }
+        Ok(())
+    }
+    fn build(patch: &DishPatch) -> Result<UpdateExpression, Error> {
+        let mut expression = UpdateExpression::default();
+        expression.change("meal", &patch.meal)?;
+        expression.change("ingredients", &patch.ingredients.as_ref().map(Some))?;
+        expression.change("spicy", &patch.spicy)?;
+        expression.change("cook_time", &patch.cook_time)?;
+        expression.change("recipe_link", &patch.recipe_link)?;
+        expression.change("serving", &patch.serving)?;
+        expression.change("one_dish", &patch.one_dish)?;
+        expression.change("soup", &patch.soup)?;
+        expression.change("style", &patch.style)?;
+        expression.change("photo", &patch.photo)?;
+        expression.change("suppressed", &patch.suppressed)?;
+        Ok(expression)
+    }
+    /// `None` when the patch changes nothing.
+    fn expression(&self) -> Option<String> {
+        let mut clauses = vec![];
+        if !self.set.is_empty() {
+            clauses.push(format!("SET {}", self.set.join(", ")));
+        }
+        if !self.remove.is_empty() {
+            clauses.push(format!("REMOVE {}", self.remove.join(", ")));
+        }
+        (!clauses.is_empty()).then(|| clauses.join(" "))
+    }
+}
+
+/// Action writing one dish, conditional on `condition`.
+fn dish_update(item: &Dish, condition: WriteCondition) -> Result<TransactWriteItem, Error> {
+    let mut update = Update::builder()
+        .table_name(TABLE_NAME)
+        .key("name", AttributeValue::S(item.name.clone()))
+        .update_expression(UPDATE_EXP);
+    if let Some(photo) = &item.photo {
+        update = update
+            .set_update_expression(Some(UPDATE_EXP_WITH_PHOTO.to_string()))
+            .expression_attribute_values(":photo", to_attribute_value(photo)?);
+    }
+    let update = update
+        .condition_expression(key_condition(condition))
+        .expression_attribute_names("#key", "name")
+        .expression_attribute_names("#st", "style")
+        .expression_attribute_values(":meal", to_attribute_value(item.meal)?)
+        .expression_attribute_values(":ingredients", to_attribute_value(&item.ingredients)?)
+        .expression_attribute_values(":spicy", to_attribute_value(item.spicy)?)
+        .expression_attribute_values(":cook_time", to_attribute_value(item.cook_time)?)
+        .expression_attribute_values(":recipe_link", to_attribute_value(&item.recipe_link)?)
+        .expression_attribute_values(":serving", to_attribute_value(item.serving)?)
+        .expression_attribute_values(":one_dish", to_attribute_value(item.one_dish)?)
+        .expression_attribute_values(":soup", to_attribute_value(item.soup)?)
+        .expression_attribute_values(":style", to_attribute_value(&item.style)?)
+        .expression_attribute_values(":suppressed", to_attribute_value(item.suppressed)?)
+        .build();
+    Ok(TransactWriteItem::builder().update(update).build())
+}
+
+/// Action applying one patch, conditional on the dish existing. A patch
+/// that changes nothing only checks that.
+fn dish_patch(patch: &DishPatch) -> Result<TransactWriteItem, Error> {
+    let key = AttributeValue::S(patch.name.clone());
+    let mut update = UpdateExpression::build(patch)?;
+    update.names.insert("#key".to_string(), "name".to_string());
+    let Some(expression) = update.expression() else {
+        let check = ConditionCheck::builder()
+            .table_name(TABLE_NAME)
+            .key("name", key)
+            .condition_expression(key_condition(WriteCondition::Present))
+            .set_expression_attribute_names(Some(update.names))
+            .build();
+        return Ok(TransactWriteItem::builder().condition_check(check).build());
+    };
+    let update = Update::builder()
+        .table_name(TABLE_NAME)
+        .key("name", key)
+        .update_expression(expression)
+        .condition_expression(key_condition(WriteCondition::Present))
+        .set_expression_attribute_names(Some(update.names))
+        // DynamoDB rejects an empty map, which a patch only clearing fields has
+        .set_expression_attribute_values((!update.values.is_empty()).then_some(update.values))
+        .build();
+    Ok(TransactWriteItem::builder().update(update).build())
+}
+
+/// Writes `actions`, one to each dish of `recipes`, as `mode` says,
+/// creating the ones of `new_ingredients` the dishes name. Atomic writes
+/// create them in the same transaction. Best effort ones create them first,
+/// writing no dish when that fails, so the new ingredients of a dish whose
+/// condition fails are left created. Returns whether each action held.
+async fn write_with_ingredients(
+    db_client: &impl DynamoDbCalls,
+    actions: Vec<TransactWriteItem>,
+    recipes: Vec<Option<&[RawRecipeIngredient]>>,
+    mode: BatchMode,
+    new_ingredients: &[Ingredient],
+) -> Result<Vec<bool>, Error> {
+    let creates: Vec<TransactWriteItem> = named_in(new_ingredients, recipes.into_iter().flatten())
+        .iter()
+        .map(ingredient_create)
+        .collect::<Result<_, _>>()?;
+    match mode {
+        BatchMode::Atomic => {
+            let count = actions.len();
+            let mut actions = actions;
+            actions.extend(creates);
+            let mut held = transact(db_client, actions).await?;
+            held.truncate(count);
+            Ok(held)
+        }
+        BatchMode::BestEffort => {
+            // Which ingredients went in is no concern of the dishes' result
+            if let Err(err) = transact_each(db_client, creates).await {
+                return Err(match err.downcast::<PartialBatch>() {
+                    Ok(partial) => partial.error,
+                    Err(err) => err,
+                });
+            }
+            transact_each(db_client, actions).await
+        }
+    }
+}
+
+impl DynamoDbStore {
+    /// Dishes whose recipe names ingredient `from`, rewritten to name `to`,
+    /// and the actions writing them.
+    pub(super) async fn rename_in_recipes(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Result<(Vec<Dish>, Vec<TransactWriteItem>), Error> {
+        self.rewrite_recipes(&[from.to_string()], |lines| {
+            for line in lines.iter_mut().filter(|line| line.name == from) {
+                line.name = to.to_string();
+            }
+        })
+        .await
+    }
+    /// Dishes whose recipe names any of `names`, passed through `rewrite`,
+    /// and the actions writing them.
+    pub(super) async fn rewrite_recipes(
+        &self,
+        names: &[String],
+        rewrite: impl Fn(&mut Vec<RawRecipeIngredient>),
+    ) -> Result<(Vec<Dish>, Vec<TransactWriteItem>), Error> {
+        let names_any =
+            |lines: &[RawRecipeIngredient]| lines.iter().any(|line| names.contains(&line.name));
+        self.rewrite_matching_recipes(names_any, rewrite).await
+    }
+    /// Dishes whose recipe `select` picks, passed through `rewrite`, and
+    /// the actions writing those whose recipe is then stored differently.
+    /// Each action fails when the recipe changed since it was scanned or
+    /// the dish is gone.
+    async fn rewrite_matching_recipes(
+        &self,
+        select: impl Fn(&[RawRecipeIngredient]) -> bool,
+        rewrite: impl Fn(&mut Vec<RawRecipeIngredient>),
+    ) -> Result<(Vec<Dish>, Vec<TransactWriteItem>), Error> {
+        let mut dishes = vec![];
+        let mut actions = vec![];
+        let mut after = None;
+        loop {
+            let output = self
+                .db_client
+                .scan()
+                .table_name(TABLE_NAME)
+                .consistent_read(true)
+                .set_exclusive_start_key(after)
+                .send()
+                .await?;
+            for item in output.items.unwrap_or_default() {
+                let mut dish: Dish = from_item(item.clone())?;
+                if !select(&dish.ingredients) {
+                    continue;
+                }
+                rewrite(&mut dish.ingredients);
+                // Compared as stored, which may predate parsed quantities
+                let scanned = item
+                    .get("ingredients")
+                    .ok_or_else(|| anyhow!("dish {} without ingredients", dish.name))?;
+                let ingredients = to_attribute_value(&dish.ingredients)?;
+                if ingredients == *scanned {
+                    continue;
+                }
+                // A dish that is gone has no ingredients to compare
+                let update = Update::builder()
+                    .table_name(TABLE_NAME)
+                    .key("name", AttributeValue::S(dish.name.clone()))
+                    .update_expression("SET ingredients = :ingredients")
+                    .condition_expression("ingredients = :scanned")
+                    .expression_attribute_values(":ingredients", ingredients)
+                    .expression_attribute_values(":scanned", scanned.clone())
+                    .build();
+                actions.push(TransactWriteItem::builder().update(update).build());
+                dishes.push(dish);
+            }
+            match output.last_evaluated_key {
+                Some(key) => after = Some(key),
+                None => return Ok((dishes, actions)),
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl DishStore for DynamoDbStore {
+    async fn get_many(&self, names: &[String]) -> Result<Vec<Dish>, Error> {
+        batch_get(&self.db_client, TABLE_NAME, string_keys("name", names)).await
+    }
+    async fn scan_page(
+        &self,
+        filter: Option<&DishFilter>,
+        after: Option<String>,
+        limit: Option<usize>,
+    ) -> Result<Page<Dish>, Error> {
+        let mut expression = FilterExpression::default();
+        let filter_expression = filter.map(|f| expression.build(f)).transpose()?.flatten();
+        let output = self
+            .db_client
+            .scan()
+            .table_name(TABLE_NAME)
+            .set_exclusive_start_key(after.map(|name| {
+                let mut map = HashMap::new();
+                map.insert("name".to_string(), AttributeValue::S(name));
+                map
+            }))
+            .set_limit(limit.map(|l| l as i32))
+            .set_filter_expression(filter_expression.clone())
+            .set_expression_attribute_names(filter_expression.as_ref().map(|_| expression.names))
+            .set_expression_attribute_values(filter_expression.map(|_| expression.values))
+            .send()
+            .await?;
+        let mut items: Vec<Dish> = from_items(output.items.unwrap_or_default())?;
+        // Not every predicate can be pushed down, so check them all again
+        if let Some(filter) = filter {
+            items.retain(|dish| filter.matches(dish));
+        }
+        Ok(Page {
+            items,
+            next_cursor: last_evaluated_name(output.last_evaluated_key),
+        })
+    }
+    async fn count(&self, filter: Option<&DishFilter>) -> Result<usize, Error> {
+        match filter {
+            Some(filter) => Ok(self.scan(Some(filter)).await?.len()),
+            None => count(&self.db_client, TABLE_NAME).await,
+        }
+    }
+    async fn migrate_quantities(&self) -> Result<usize, Error> {
+        // Rewriting nothing stores a recipe as it is read, that is parsed
+        let (_, actions) = self.rewrite_matching_recipes(|_| true, |_| {}).await?;
+        let held = transact_each(&self.db_client, actions).await?;
+        Ok(held.into_iter().filter(|held| *held).count())
+    }
+    async fn update_many_if(
+        &self,
+        items: &[Dish],
+        condition: WriteCondition,
+        mode: BatchMode,
+        new_ingredients: &[Ingredient],
+    ) -> Result<Vec<bool>, Error> {
+        let actions = items
+            .iter()
+            .map(|item| dish_update(item, condition))
+            .collect::<Result<_, _>>()?;
+        let recipes = items.iter().map(|item| Some(item.ingredients.as_slice()));
+        write_with_ingredients(
+            &self.db_client,
+            actions,
+            recipes.collect(),
+            mode,
+            new_ingredients,
+        )
+        .await
+    }
+    async fn patch_many(
+        &self,
+        patches: &[DishPatch],
+        mode: BatchMode,
+        new_ingredients: &[Ingredient],
+    ) -> Result<Vec<bool>, Error> {
+        let actions = patches.iter().map(dish_patch).collect::<Result<_, _>>()?;
+        let recipes = patches.iter().map(|patch| patch.ingredients.as_deref());
+        write_with_ingredients(
+            &self.db_client,
+            actions,
+            recipes.collect(),
+            mode,
+            new_ingredients,
+        )
+        .await
+    }
+    async fn rename(&self, from: &str, to: &str) -> Result<(), Error> {
+        let item = get_raw(&self.db_client, TABLE_NAME, ("name", from)).await?;
+        let taken = get_raw(&self.db_client, TABLE_NAME, ("name", to)).await?;
+        check_rename("dish", from, to, item.is_some(), taken.is_some())?;
+        let mut actions = self.move_cook_log(from, to).await?;
+        actions.append(&mut self.rename_in_meal_plans(from, to).await?);
+        let item = item.expect("dish to rename");
+        actions.extend(move_item(TABLE_NAME, &["name"], ATTRIBUTES, item, to)?);
+        check_rename_size("dish", from, actions.len())?;
+        let held = transact(&self.db_client, actions).await?;
+        if !held.iter().all(|held| *held) {
+            return Err(anyhow!(
+                "dish {} was changed while renaming it, try again",
+                from
+            ));
+        }
+        Ok(())
+    }
+    async fn delete_many(&self, names: &[String]) -> Result<(), Error> {
+        let actions = names
+            .iter()
+            .map(|name| {
+                let delete = Delete::builder()
+                    .table_name(TABLE_NAME)
+                    .key("name", AttributeValue::S(name.clone()))
+                    .build();
+                TransactWriteItem::builder().delete(delete).build()
+            })
+            .collect();
+        transact_each(&self.db_client, actions).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data_sources::dynamodb::{tests::FakeDb, MAX_TRANSACT_ITEMS},
+        graphql::{Meal, Quantity},
+    };
+
+    fn build(filter: &DishFilter) -> (Option<String>, FilterExpression) {
+        let mut expression = FilterExpression::default();
+        let text = expression.build(filter).unwrap();
+        (text, expression)
+    }
+
+    /// DynamoDB rejects names and values the expression does not use.
+    fn assert_all_used(text: &str, expression: &FilterExpression) {
+        for placeholder in expression.names.keys().chain(expression.values.keys()) {
+            assert!(
+                text.contains(placeholder.as_str()),
+                "{} unused in {}",
+                placeholder,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn empty_filter_pushes_nothing_down() {
+        let (text, expression) = build(&DishFilter::default());
+        assert_eq!(text, None);
+        assert!(expression.names.is_empty() && expression.values.is_empty());
+    }
+
+    #[test]
+    fn predicates_are_joined_with_and() {
+        let (text, expression) = build(&DishFilter {
+            meal: Some(Meal::Lunch),
+            soup: Some(false),
+            max_cook_time: Some(20),
+            ..DishFilter::default()
+        });
+        let text = text.unwrap();
+        assert_eq!(
+            text,
+            "(#meal = :f0 AND NOT #soup = :f1 AND #cook_time <= :f2)"
+        );
+        assert_all_used(&text, &expression);
+        assert_eq!(expression.values.len(), 3);
+    }
+
+    #[test]
+    fn or_alternatives_share_placeholder_numbering() {
+        let (text, expression) = build(&DishFilter {
+            meal: Some(Meal::Dinner),
+            or: Some(vec![
+                DishFilter {
+                    soup: Some(true),
+                    ..DishFilter::default()
+                },
+                DishFilter {
+                    style: Some("川菜".to_string()),
+                    ..DishFilter::default()
+                },
+            ]),
+            ..DishFilter::default()
+        });
+        let text = text.unwrap();
+        assert_eq!(text, "(#meal = :f0 AND ((#soup = :f1) OR (#style = :f2)))");
+        assert_all_used(&text, &expression);
+    }
+
+    #[test]
+    fn or_with_an_untranslatable_alternative_leaves_nothing_behind() {
+        let (text, expression) = build(&DishFilter {
+            meal: Some(Meal::Lunch),
+            or: Some(vec![
+                DishFilter {
+                    soup: Some(true),
+                    ..DishFilter::default()
+                },
+                DishFilter {
+                    ingredient: Some("x".to_string()),
+                    ..DishFilter::default()
+                },
+            ]),
+            ..DishFilter::default()
+        });
+        let text = text.unwrap();
+        assert_eq!(text, "(#meal = :f0)");
+        assert_all_used(&text, &expression);
+        assert_eq!(expression.names.len(), 1);
+        assert_eq!(expression.values.len(), 1);
+    }
+
+    #[test]
+    fn ingredient_only_filter_is_not_pushed_down() {
+        let (text, _) = build(&DishFilter {
+            ingredient: Some("x".to_string()),
+            ..DishFilter::default()
+        });
+        assert_eq!(text, None);
+    }
+
+    fn patch(patch: DishPatch) -> TransactWriteItem {
+        dish_patch(&DishPatch {
+            name: "番茄炒蛋".to_string(),
+            ..patch
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn clearing_patch_sends_no_values() {
+        let action = patch(DishPatch {
+            spicy: Some(None),
+            style: Some(None),
+            ..DishPatch::default()
+        });
+        let update = action.update.unwrap();
+        assert_eq!(
+            update.update_expression.as_deref(),
+            Some("REMOVE #spicy, #style")
+        );
+        assert_eq!(update.expression_attribute_values, None);
+    }
+
+    #[test]
+    fn setting_patch_sends_its_values() {
+        let action = patch(DishPatch {
+            serving: Some(Some(2)),
+            style: Some(None),
+            ..DishPatch::default()
+        });
+        let update = action.update.unwrap();
+        assert_eq!(
+            update.update_expression.as_deref(),
+            Some("SET #serving = :serving REMOVE #style")
+        );
+        let values = update.expression_attribute_values.unwrap();
+        assert_eq!(values.keys().collect::<Vec<_>>(), [":serving"]);
+    }
+
+    #[test]
+    fn empty_patch_only_checks_the_dish_exists() {
+        let action = patch(DishPatch::default());
+        assert!(action.update.is_none());
+        let check = action.condition_check.unwrap();
+        assert_eq!(check.expression_attribute_values, None);
+        assert_eq!(
+            check.expression_attribute_names.unwrap().get("#key"),
+            Some(&"name".to_string())
+        );
+    }
+
+    /// Dishes named `names`, each naming the ingredient `{name} 料`.
+    fn dishes(names: &[String]) -> Vec<Dish> {
+        names
+            .iter()
+            .map(|name| Dish {
+                name: name.clone(),
+                ingredients: vec![RawRecipeIngredient {
+                    name: format!("{} 料", name),
+                    quantity: Quantity::parse("1个"),
+                }],
+                ..Dish::default()
+            })
+            .collect()
+    }
+
+    fn ingredient(name: &str) -> Ingredient {
+        Ingredient {
+            name: name.to_string(),
+            category: "未分类".to_string(),
+            density: None,
+            piece_weight: None,
+        }
+    }
+
+    async fn write(
+        db: &FakeDb,
+        names: &[String],
+        mode: BatchMode,
+        new_ingredients: &[&str],
+    ) -> Result<Vec<bool>, Error> {
+        let dishes = dishes(names);
+        let actions = dishes
+            .iter()
+            .map(|dish| dish_update(dish, WriteCondition::Absent))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let recipes = dishes.iter().map(|dish| Some(dish.ingredients.as_slice()));
+        let new_ingredients: Vec<Ingredient> = new_ingredients
+            .iter()
+            .map(|name| ingredient(name))
+            .collect();
+        write_with_ingredients(db, actions, recipes.collect(), mode, &new_ingredients).await
+    }
+
+    fn strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn atomic_writes_create_ingredients_with_the_dishes() {
+        let db = FakeDb::default();
+        let held = write(
+            &db,
+            &strings(&["a", "b"]),
+            BatchMode::Atomic,
+            &["b 料", "c 料"],
+        )
+        .await;
+        assert_eq!(held.unwrap(), [true, true]);
+        // Only the ingredients a dish names are created
+        assert_eq!(db.transactions(), [strings(&["a", "b", "b 料"])]);
+    }
+
+    #[tokio::test]
+    async fn best_effort_writes_create_ingredients_first() {
+        let db = FakeDb {
+            conflicts: strings(&["b"]),
+            ..FakeDb::default()
+        };
+        let names = strings(&["a", "b"]);
+        let held = write(&db, &names, BatchMode::BestEffort, &["a 料", "b 料"]).await;
+        assert_eq!(held.unwrap(), [true, false]);
+        assert_eq!(
+            db.transactions(),
+            [strings(&["a 料", "b 料"]), names, strings(&["a"])]
+        );
+    }
+
+    #[tokio::test]
+    async fn failing_to_create_ingredients_writes_no_dish() {
+        let db = FakeDb {
+            failing_call: Some(0),
+            ..FakeDb::default()
+        };
+        let result = write(&db, &strings(&["a"]), BatchMode::BestEffort, &["a 料"]).await;
+        let err = result.unwrap_err();
+        assert!(err.downcast_ref::<PartialBatch>().is_none());
+        assert_eq!(err.to_string(), "throttled");
+        assert_eq!(db.transactions(), [strings(&["a 料"])]);
+    }
+
+    #[tokio::test]
+    async fn dish_writes_cut_short_report_each_dish() {
+        // The ingredients, then two transactions of dishes, the second failing
+        let db = FakeDb {
+            failing_call: Some(2),
+            ..FakeDb::default()
+        };
+        let names: Vec<String> = (0..MAX_TRANSACT_ITEMS + 1).map(|i| i.to_string()).collect();
+        let result = write(&db, &names, BatchMode::BestEffort, &["0 料"]).await;
+        let partial = result.unwrap_err().downcast::<PartialBatch>().unwrap();
+        let mut held = vec![Some(true); MAX_TRANSACT_ITEMS];
+        held.push(None);
+        assert_eq!(partial.held, held);
+        assert_eq!(partial.error.to_string(), "throttled");
+    }
+}
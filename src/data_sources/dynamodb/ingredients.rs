@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Error};
+use aws_sdk_dynamodb::model::{AttributeValue, Delete, TransactWriteItem, Update};
+use serde_dynamo::{from_items, to_attribute_value};
+
+use super::{
+    batch_get, check_rename_size, count, get_raw, key_condition, last_evaluated_name, move_item,
+    string_keys, transact, transact_batch, transact_groups, DynamoDbStore,
+};
+use crate::{
+    data_sources::store::{check_rename, BatchMode, IngredientStore, WriteCondition},
+    graphql::{Dish, Ingredient, Page},
+};
+
+const TABLE_NAME: &str = "todays-menu-ingredients";
+
+/// Attributes of an ingredient besides its name.
+const ATTRIBUTES: &[&str] = &["category", "density", "piece_weight"];
+
+const UPDATE_EXP: &str = "SET
+    #C = :category,
+    density = :density,
+    piece_weight = :piece_weight
+";
+
+/// Action writing one ingredient, conditional on `condition`.
+fn ingredient_update(
+    item: &Ingredient,
+    condition: WriteCondition,
+) -> Result<TransactWriteItem, Error> {
+    let update = Update::builder()
+        .table_name(TABLE_NAME)
+        .key("name", AttributeValue::S(item.name.clone()))
+        .update_expression(UPDATE_EXP)
+        .condition_expression(key_condition(condition))
+        .expression_attribute_names("#key", "name")
+        .expression_attribute_names("#C", "category")
+        .expression_attribute_values(":category", AttributeValue::S(item.category.clone()))
+        .expression_attribute_values(":density", to_attribute_value(item.density)?)
+        .expression_attribute_values(":piece_weight", to_attribute_value(item.piece_weight)?)
+        .build();
+    Ok(TransactWriteItem::builder().update(update).build())
+}
+
+/// Action creating one ingredient, which leaves it as it is when it
+/// exists already.
+pub(super) fn ingredient_create(item: &Ingredient) -> Result<TransactWriteItem, Error> {
+    let update = Update::builder()
+        .table_name(TABLE_NAME)
+        .key("name", AttributeValue::S(item.name.clone()))
+        .update_expression("SET #C = if_not_exists(#C, :category)")
+        .expression_attribute_names("#C", "category")
+        .expression_attribute_values(":category", AttributeValue::S(item.category.clone()))
+        .build();
+    Ok(TransactWriteItem::builder().update(update).build())
+}
+
+#[async_trait::async_trait]
+impl IngredientStore for DynamoDbStore {
+    async fn get_many(&self, names: &[String]) -> Result<Vec<Ingredient>, Error> {
+        batch_get(&self.db_client, TABLE_NAME, string_keys("name", names)).await
+    }
+    async fn scan_page(
+        &self,
+        after: Option<String>,
+        limit: Option<usize>,
+    ) -> Result<Page<Ingredient>, Error> {
+        let output = self
+            .db_client
+            .scan()
+            .table_name(TABLE_NAME)
+            .set_exclusive_start_key(after.map(|name| {
+                let mut map = HashMap::new();
+                map.insert("name".to_string(), AttributeValue::S(name));
+                map
+            }))
+            .set_limit(limit.map(|l| l as i32))
+            .send()
+            .await?;
+        Ok(Page {
+            items: from_items(output.items.unwrap_or_default())?,
+            next_cursor: last_evaluated_name(output.last_evaluated_key),
+        })
+    }
+    async fn count(&self) -> Result<usize, Error> {
+        count(&self.db_client, TABLE_NAME).await
+    }
+    async fn update_many_if(
+        &self,
+        items: &[Ingredient],
+        condition: WriteCondition,
+        mode: BatchMode,
+    ) -> Result<Vec<bool>, Error> {
+        let actions = items
+            .iter()
+            .map(|item| ingredient_update(item, condition))
+            .collect::<Result<_, _>>()?;
+        transact_batch(&self.db_client, actions, mode).await
+    }
+    async fn rename(&self, from: &str, to: &str) -> Result<Vec<Dish>, Error> {
+        let item = get_raw(&self.db_client, TABLE_NAME, ("name", from)).await?;
+        let taken = get_raw(&self.db_client, TABLE_NAME, ("name", to)).await?;
+        check_rename("ingredient", from, to, item.is_some(), taken.is_some())?;
+        let (dishes, mut actions) = self.rename_in_recipes(from, to).await?;
+        let item = item.expect("ingredient to rename");
+        actions.extend(move_item(TABLE_NAME, &["name"], ATTRIBUTES, item, to)?);
+        actions.append(&mut self.move_pantry_item(from, to).await?);
+        check_rename_size("ingredient", from, actions.len())?;
+        let held = transact(&self.db_client, actions).await?;
+        if !held.iter().all(|held| *held) {
+            return Err(anyhow!(
+                "ingredient {} was changed while renaming it, try again",
+                from
+            ));
+        }
+        Ok(dishes)
+    }
+    async fn delete_many(&self, names: &[String]) -> Result<Vec<Dish>, Error> {
+        let (dishes, rewrites) = self
+            .rewrite_recipes(names, |lines| {
+                lines.retain(|line| !names.contains(&line.name))
+            })
+            .await?;
+        let mut groups: Vec<_> = rewrites.into_iter().map(|action| vec![action]).collect();
+        // Deleted last, so a deletion cut short is finished by running it again
+        for name in names {
+            let delete = Delete::builder()
+                .table_name(TABLE_NAME)
+                .key("name", AttributeValue::S(name.clone()))
+                .build();
+            groups.push(vec![TransactWriteItem::builder().delete(delete).build()]);
+        }
+        if !transact_groups(&self.db_client, groups).await? {
+            return Err(anyhow!(
+                "a recipe was changed while deleting ingredients, try again"
+            ));
+        }
+        Ok(dishes)
+    }
+}
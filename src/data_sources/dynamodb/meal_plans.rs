@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Error};
+use aws_sdk_dynamodb::model::{AttributeValue, Put, TransactWriteItem};
+use chrono::NaiveDate;
+use serde_dynamo::{from_item, to_attribute_value, to_item};
+
+use super::{batch_get, DynamoDbStore};
+use crate::{
+    data_sources::store::{rename_in, MealPlanStore},
+    graphql::{Meal, MealSlot},
+};
+
+const TABLE_NAME: &str = "todays-menu-meal-plans";
+
+impl DynamoDbStore {
+    /// Actions rewriting the slots that name dish `from` to name `to`.
+    pub(super) async fn rename_in_meal_plans(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<TransactWriteItem>, Error> {
+        let mut actions = vec![];
+        let mut after = None;
+        loop {
+            let output = self
+                .db_client
+                .scan()
+                .table_name(TABLE_NAME)
+                .filter_expression("contains(dish_names, :name)")
+                .expression_attribute_values(":name", AttributeValue::S(from.to_string()))
+                .consistent_read(true)
+                .set_exclusive_start_key(after)
+                .send()
+                .await?;
+            for item in output.items.unwrap_or_default() {
+                let mut slot: MealSlot = from_item(item.clone())?;
+                rename_in(&mut slot.dish_names, from, to);
+                // Only the dishes it was scanned with are renamed
+                let scanned = item
+                    .get("dish_names")
+                    .ok_or_else(|| anyhow!("meal slot without dish_names"))?;
+                let put = Put::builder()
+                    .table_name(TABLE_NAME)
+                    .set_item(Some(to_item(&slot)?))
+                    .condition_expression("dish_names = :scanned")
+                    .expression_attribute_values(":scanned", scanned.clone())
+                    .build();
+                actions.push(TransactWriteItem::builder().put(put).build());
+            }
+            match output.last_evaluated_key {
+                Some(key) => after = Some(key),
+                None => return Ok(actions),
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl MealPlanStore for DynamoDbStore {
+    async fn get_range(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<MealSlot>, Error> {
+        // Every slot in the range is known up front, so fetching them by key
+        // avoids scanning the whole table
+        let mut keys = vec![];
+        for date in from.iter_days().take_while(|date| *date <= to) {
+            for meal in [Meal::Lunch, Meal::Dinner] {
+                let mut map = HashMap::new();
+                map.insert("date".to_string(), to_attribute_value(date)?);
+                map.insert("meal".to_string(), to_attribute_value(meal)?);
+                keys.push(map);
+            }
+        }
+        let mut slots: Vec<MealSlot> = batch_get(&self.db_client, TABLE_NAME, keys).await?;
+        slots.sort_by_key(|slot| (slot.date, slot.meal));
+        Ok(slots)
+    }
+    async fn put_many(&self, slots: &[MealSlot]) -> Result<(), Error> {
+        for slot in slots {
+            if slot.dish_names.is_empty() {
+                self.db_client
+                    .delete_item()
+                    .table_name(TABLE_NAME)
+                    .key("date", to_attribute_value(slot.date)?)
+                    .key("meal", to_attribute_value(slot.meal)?)
+                    .send()
+                    .await?;
+            } else {
+                self.db_client
+                    .put_item()
+                    .table_name(TABLE_NAME)
+                    .set_item(Some(to_item(slot)?))
+                    .send()
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use anyhow::Error;
+use aws_sdk_dynamodb::model::{AttributeValue, TransactWriteItem};
+use serde_dynamo::{from_items, to_item};
+
+use super::{batch_get, get_raw, move_item, string_keys, DynamoDbStore};
+use crate::{data_sources::store::PantryStore, graphql::PantryItem};
+
+const TABLE_NAME: &str = "todays-menu-pantry";
+
+/// Attributes of a pantry item besides its ingredient name.
+const ATTRIBUTES: &[&str] = &["quantity", "unit", "purchased_on", "expires_on"];
+
+impl DynamoDbStore {
+    /// Actions moving the stock of ingredient `from` to ingredient `to`.
+    pub(super) async fn move_pantry_item(
+        &self,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<TransactWriteItem>, Error> {
+        match get_raw(&self.db_client, TABLE_NAME, ("ingredient_name", from)).await? {
+            Some(item) => {
+                Ok(move_item(TABLE_NAME, &["ingredient_name"], ATTRIBUTES, item, to)?.to_vec())
+            }
+            None => Ok(vec![]),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl PantryStore for DynamoDbStore {
+    async fn get_many(&self, ingredient_names: &[String]) -> Result<Vec<PantryItem>, Error> {
+        let keys = string_keys("ingredient_name", ingredient_names);
+        batch_get(&self.db_client, TABLE_NAME, keys).await
+    }
+    async fn scan(&self) -> Result<Vec<PantryItem>, Error> {
+        let mut items = vec![];
+        let mut after = None;
+        loop {
+            let output = self
+                .db_client
+                .scan()
+                .table_name(TABLE_NAME)
+                .set_exclusive_start_key(after)
+                .send()
+                .await?;
+            items.append(&mut from_items(output.items.unwrap_or_default())?);
+            match output.last_evaluated_key {
+                Some(key) => after = Some(key),
+                None => return Ok(items),
+            }
+        }
+    }
+    async fn put_many(&self, items: &[PantryItem]) -> Result<(), Error> {
+        for item in items {
+            self.db_client
+                .put_item()
+                .table_name(TABLE_NAME)
+                .set_item(Some(to_item(item)?))
+                .send()
+                .await?;
+        }
+        Ok(())
+    }
+    async fn delete_many(&self, ingredient_names: &[String]) -> Result<(), Error> {
+        for name in ingredient_names {
+            self.db_client
+                .delete_item()
+                .table_name(TABLE_NAME)
+                .key("ingredient_name", AttributeValue::S(name.clone()))
+                .send()
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+use anyhow::Error;
+use aws_sdk_dynamodb::model::AttributeValue;
+use serde_dynamo::to_item;
+
+use super::{batch_get, string_keys, DynamoDbStore};
+use crate::{data_sources::store::ShareStore, graphql::ShareableMenu};
+
+const TABLE_NAME: &str = "todays-menu-shares";
+
+#[async_trait::async_trait]
+impl ShareStore for DynamoDbStore {
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<ShareableMenu>, Error> {
+        batch_get(&self.db_client, TABLE_NAME, string_keys("key", keys)).await
+    }
+    async fn put(&self, item: &ShareableMenu) -> Result<(), Error> {
+        self.db_client
+            .put_item()
+            .table_name(TABLE_NAME)
+            .set_item(Some(to_item(item)?))
+            .send()
+            .await?;
+        Ok(())
+    }
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.db_client
+            .delete_item()
+            .table_name(TABLE_NAME)
+            .key("key", AttributeValue::S(key.to_owned()))
+            .send()
+            .await?;
+        Ok(())
+    }
+}
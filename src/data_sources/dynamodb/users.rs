@@ -0,0 +1,22 @@
+use anyhow::Error;
+use aws_sdk_dynamodb::model::AttributeValue;
+use serde_dynamo::from_item;
+
+use super::DynamoDbStore;
+use crate::data_sources::store::{UserRecord, UserStore};
+
+const TABLE_NAME: &str = "todays-menu-users";
+
+#[async_trait::async_trait]
+impl UserStore for DynamoDbStore {
+    async fn get(&self, user_id: &str) -> Result<Option<UserRecord>, Error> {
+        let output = self
+            .db_client
+            .get_item()
+            .table_name(TABLE_NAME)
+            .key("user_id", AttributeValue::S(user_id.to_owned()))
+            .send()
+            .await?;
+        Ok(output.item.map(from_item).transpose()?)
+    }
+}
@@ -2,22 +2,17 @@ use std::{collections::HashMap, sync::Arc};
 
 use anyhow::Error;
 use async_graphql::dataloader::Loader;
-use aws_sdk_dynamodb::{
-    model::{AttributeValue, KeysAndAttributes},
-    Client,
-};
-use serde_dynamo::from_items;
-
-use crate::graphql::{Ingredient, IngredientInput};
-
-const TABLE_NAME: &str = "todays-menu-ingredients";
 
-const UPDATE_EXP: &str = "SET
-    #C = :category
-";
+use super::{
+    paging::{slice, walk, Window},
+    search::SearchIndex,
+    store::{BatchMode, IngredientStore, WriteCondition},
+};
+use crate::graphql::{Ingredient, IngredientInput, IngredientOrder, Page};
 
 pub(crate) struct IngredientLoader {
-    db_client: Client,
+    store: Arc<dyn IngredientStore>,
+    index: Arc<SearchIndex>,
 }
 
 #[async_trait::async_trait]
@@ -26,66 +21,138 @@ impl Loader<String> for IngredientLoader {
     type Error = Arc<Error>;
 
     async fn load(&self, keys: &[String]) -> Result<HashMap<String, Ingredient>, Self::Error> {
-        let mut ingredients: Vec<Ingredient> = vec![];
-        for keys in keys.chunks(100) {
-            let keys = KeysAndAttributes::builder()
-                .set_keys(Some(
-                    keys.iter()
-                        .map(|k| {
-                            let mut map = HashMap::new();
-                            map.insert("name".to_string(), AttributeValue::S(k.to_owned()));
-                            map
-                        })
-                        .collect(),
-                ))
-                .build();
-            let output = self
-                .db_client
-                .batch_get_item()
-                .request_items(TABLE_NAME, keys)
-                .send()
-                .await
-                .map_err(|e| Arc::new(e.into()))?;
-            if let Some(mut items) = output.responses {
-                let items = items.remove(TABLE_NAME).expect("responses");
-                ingredients.append(&mut from_items(items).map_err(|e| Arc::new(e.into()))?);
-            }
-        }
+        let ingredients = self.store.get_many(keys).await.map_err(Arc::new)?;
         Ok(ingredients
             .into_iter()
             .map(|item| (item.name.clone(), item))
             .collect())
-        // TODO process unprocessed keys
     }
 }
 
 impl IngredientLoader {
-    pub(crate) fn new(dynamodb: Client) -> IngredientLoader {
-        IngredientLoader {
-            db_client: dynamodb,
+    pub(crate) fn new(
+        store: Arc<dyn IngredientStore>,
+        index: Arc<SearchIndex>,
+    ) -> IngredientLoader {
+        IngredientLoader { store, index }
+    }
+    /// Without `order` ingredients come in the order the store scans them.
+    pub(crate) async fn load_all(
+        &self,
+        order: Option<&IngredientOrder>,
+    ) -> Result<Vec<Ingredient>, Error> {
+        let mut ingredients = self.store.scan().await?;
+        if let Some(order) = order {
+            ingredients.sort_by(|a, b| order.compare(a, b));
         }
+        Ok(ingredients)
     }
-    pub(crate) async fn load_all(&self) -> Result<Vec<Ingredient>, Error> {
-        let output = self.db_client.scan().table_name(TABLE_NAME).send().await?;
-        match output.items {
-            Some(items) => Ok(from_items(items)?),
-            None => Ok(vec![]),
+    pub(crate) async fn load_page(
+        &self,
+        order: Option<&IngredientOrder>,
+        after: Option<String>,
+        limit: usize,
+    ) -> Result<Page<Ingredient>, Error> {
+        match order {
+            Some(_) => Ok(self
+                .load_window(order, after, None, Some(limit), None)
+                .await?
+                .into_page(|item| &item.name)),
+            None => self.store.scan_page(after, Some(limit)).await,
         }
     }
-    pub(crate) async fn update_many(&self, items: &[IngredientInput]) -> Result<(), Error> {
-        for item in items {
-            self.db_client
-                .update_item()
-                .table_name(TABLE_NAME)
-                .key("name", AttributeValue::S(item.name.clone()))
-                .update_expression(UPDATE_EXP)
-                .expression_attribute_names("#C", "category")
-                .expression_attribute_values(":category", AttributeValue::S(item.category.clone()))
-                .send()
-                .await?;
+    /// Sorting needs every ingredient, only unsorted windows are read page
+    /// by page.
+    pub(crate) async fn load_window(
+        &self,
+        order: Option<&IngredientOrder>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<usize>,
+        last: Option<usize>,
+    ) -> Result<Window<Ingredient>, Error> {
+        match order {
+            Some(_) => slice(
+                self.load_all(order).await?,
+                |item| &item.name,
+                after,
+                before,
+                first,
+                last,
+            ),
+            None => {
+                walk(
+                    |after, limit| self.store.scan_page(after, limit),
+                    |item| &item.name,
+                    after,
+                    before,
+                    first,
+                    last,
+                )
+                .await
+            }
         }
-
-        // TODO handle error
-        Ok(())
+    }
+    pub(crate) async fn count(&self) -> Result<usize, Error> {
+        self.store.count().await
+    }
+    /// Writes ingredients when `condition` holds, as `mode` says. Returns
+    /// whether it held for each ingredient, in order.
+    pub(crate) async fn write_many(
+        &self,
+        items: &[IngredientInput],
+        condition: WriteCondition,
+        mode: BatchMode,
+    ) -> Result<Vec<bool>, Error> {
+        let ingredients: Vec<Ingredient> = items
+            .iter()
+            .map(|item| Ingredient {
+                name: item.name.clone(),
+                category: item.category.clone(),
+                density: item.density,
+                piece_weight: item.piece_weight,
+            })
+            .collect();
+        let result = self
+            .store
+            .update_many_if(&ingredients, condition, mode)
+            .await;
+        let updated: Vec<Ingredient> = ingredients
+            .into_iter()
+            .zip(mode.written(&result, items.len()))
+            .filter(|(_, written)| *written)
+            .map(|(ingredient, _)| ingredient)
+            .collect();
+        self.index.update_ingredients(&updated);
+        result
+    }
+    /// Renames ingredient `from` to `to`, rewriting the recipes naming it.
+    /// Returns the number of dishes rewritten.
+    pub(crate) async fn rename(&self, from: &str, to: &str) -> Result<usize, Error> {
+        let rewritten = self.store.rename(from, to).await?;
+        self.index.remove_ingredients(&[from.to_string()]);
+        let renamed = self.store.get_many(&[to.to_string()]).await?;
+        self.index.update_ingredients(&renamed);
+        self.index.update_dishes(&rewritten);
+        Ok(rewritten.len())
+    }
+    /// Deletes the ingredients that exist in `names` and the recipe lines
+    /// naming them. Returns the names of the ingredients deleted and the
+    /// number of dishes rewritten.
+    pub(crate) async fn delete_many(
+        &self,
+        names: &[String],
+    ) -> Result<(Vec<String>, usize), Error> {
+        let deleted: Vec<String> = self
+            .store
+            .get_many(names)
+            .await?
+            .into_iter()
+            .map(|ingredient| ingredient.name)
+            .collect();
+        let rewritten = self.store.delete_many(&deleted).await?;
+        self.index.remove_ingredients(&deleted);
+        self.index.update_dishes(&rewritten);
+        Ok((deleted, rewritten.len()))
     }
 }
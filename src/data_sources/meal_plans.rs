@@ -0,0 +1,287 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Error};
+use chrono::NaiveDate;
+
+use super::store::MealPlanStore;
+use crate::graphql::{Meal, MealSlot};
+
+/// Longest range read or cleared at once, a year including a leap day.
+const MAX_DAYS: i64 = 366;
+
+const MEALS: [Meal; 2] = [Meal::Lunch, Meal::Dinner];
+
+/// Reads and edits the meal plan calendar.
+pub(crate) struct MealPlanner {
+    store: Arc<dyn MealPlanStore>,
+}
+
+impl MealPlanner {
+    pub(crate) fn new(store: Arc<dyn MealPlanStore>) -> MealPlanner {
+        MealPlanner { store }
+    }
+
+    /// Every slot from `from` to `to`, both included, ordered by date and
+    /// meal. Slots nothing is planned for hold no dishes.
+    pub(crate) async fn load_range(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<MealSlot>, Error> {
+        check_range(from, to)?;
+        let mut planned = self.store.get_range(from, to).await?;
+        let mut slots = vec![];
+        for date in from.iter_days().take_while(|date| *date <= to) {
+            for meal in MEALS {
+                let dish_names = match planned
+                    .iter()
+                    .position(|s| s.date == date && s.meal == meal)
+                {
+                    Some(i) => planned.swap_remove(i).dish_names,
+                    None => vec![],
+                };
+                slots.push(MealSlot {
+                    date,
+                    meal,
+                    dish_names,
+                });
+            }
+        }
+        Ok(slots)
+    }
+
+    /// Plans `dishes` for the slot, replacing what was planned unless
+    /// `append` is set.
+    pub(crate) async fn assign(
+        &self,
+        date: NaiveDate,
+        meal: Meal,
+        dishes: Vec<String>,
+        append: bool,
+    ) -> Result<(), Error> {
+        let mut slot = self.load_one(date, meal).await?;
+        if !append {
+            slot.dish_names.clear();
+        }
+        extend_unique(&mut slot.dish_names, dishes);
+        self.store.put_many(&[slot]).await
+    }
+
+    /// Moves the dishes of one slot to another, after the dishes already
+    /// planned there. The slot moved from is left empty.
+    pub(crate) async fn move_slot(
+        &self,
+        from: (NaiveDate, Meal),
+        to: (NaiveDate, Meal),
+    ) -> Result<(), Error> {
+        if from == to {
+            return Ok(());
+        }
+        let mut source = self.load_one(from.0, from.1).await?;
+        let mut target = self.load_one(to.0, to.1).await?;
+        extend_unique(&mut target.dish_names, source.dish_names.drain(..));
+        self.store.put_many(&[target, source]).await
+    }
+
+    /// Empties the slots from `from` to `to`, both included, or only those
+    /// for `meal`. Returns the number of slots that held dishes.
+    pub(crate) async fn clear(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        meal: Option<Meal>,
+    ) -> Result<usize, Error> {
+        check_range(from, to)?;
+        let cleared: Vec<MealSlot> = self
+            .store
+            .get_range(from, to)
+            .await?
+            .into_iter()
+            .filter(|slot| meal.is_none_or(|m| m == slot.meal))
+            .map(|slot| MealSlot {
+                dish_names: vec![],
+                ..slot
+            })
+            .collect();
+        self.store.put_many(&cleared).await?;
+        Ok(cleared.len())
+    }
+
+    async fn load_one(&self, date: NaiveDate, meal: Meal) -> Result<MealSlot, Error> {
+        Ok(self
+            .store
+            .get_range(date, date)
+            .await?
+            .into_iter()
+            .find(|slot| slot.meal == meal)
+            .unwrap_or(MealSlot {
+                date,
+                meal,
+                dish_names: vec![],
+            }))
+    }
+}
+
+fn check_range(from: NaiveDate, to: NaiveDate) -> Result<(), Error> {
+    if to < from {
+        return Err(anyhow!("range ends before it starts"));
+    }
+    if (to - from).num_days() >= MAX_DAYS {
+        return Err(anyhow!("range spans more than {} days", MAX_DAYS));
+    }
+    Ok(())
+}
+
+/// A dish is planned at most once per slot.
+fn extend_unique(dishes: &mut Vec<String>, more: impl IntoIterator<Item = String>) {
+    for dish in more {
+        if !dishes.contains(&dish) {
+            dishes.push(dish);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sources::memory::MemoryStore;
+
+    fn date(text: &str) -> NaiveDate {
+        text.parse().unwrap()
+    }
+
+    fn names(dishes: &[&str]) -> Vec<String> {
+        dishes.iter().map(|dish| dish.to_string()).collect()
+    }
+
+    fn planner() -> MealPlanner {
+        MealPlanner::new(Arc::new(MemoryStore::new()))
+    }
+
+    fn meal_name(meal: Meal) -> &'static str {
+        match meal {
+            Meal::Lunch => "lunch",
+            Meal::Dinner => "dinner",
+        }
+    }
+
+    async fn dishes(planner: &MealPlanner, day: &str, meal: Meal) -> Vec<String> {
+        planner.load_one(date(day), meal).await.unwrap().dish_names
+    }
+
+    #[test]
+    fn ranges_are_ordered_and_at_most_a_year_long() {
+        assert!(check_range(date("2024-01-01"), date("2024-01-01")).is_ok());
+        assert!(check_range(date("2024-01-01"), date("2024-12-31")).is_ok());
+        let backwards = check_range(date("2024-01-02"), date("2024-01-01"));
+        assert_eq!(
+            backwards.unwrap_err().to_string(),
+            "range ends before it starts"
+        );
+        let too_long = check_range(date("2024-01-01"), date("2025-01-01"));
+        assert_eq!(
+            too_long.unwrap_err().to_string(),
+            "range spans more than 366 days"
+        );
+    }
+
+    #[test]
+    fn dishes_are_added_once() {
+        let mut dishes = names(&["红烧肉", "炒青菜"]);
+        extend_unique(&mut dishes, names(&["炒青菜", "番茄蛋汤", "番茄蛋汤"]));
+        assert_eq!(dishes, names(&["红烧肉", "炒青菜", "番茄蛋汤"]));
+    }
+
+    #[tokio::test]
+    async fn ranges_hold_every_slot_in_order() {
+        let planner = planner();
+        planner
+            .assign(date("2024-03-02"), Meal::Lunch, names(&["红烧肉"]), false)
+            .await
+            .unwrap();
+        let slots = planner
+            .load_range(date("2024-03-01"), date("2024-03-02"))
+            .await
+            .unwrap();
+        let listed: Vec<(NaiveDate, &str, Vec<String>)> = slots
+            .into_iter()
+            .map(|slot| (slot.date, meal_name(slot.meal), slot.dish_names))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                (date("2024-03-01"), "lunch", vec![]),
+                (date("2024-03-01"), "dinner", vec![]),
+                (date("2024-03-02"), "lunch", names(&["红烧肉"])),
+                (date("2024-03-02"), "dinner", vec![]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn assigning_replaces_unless_appending() {
+        let planner = planner();
+        let day = date("2024-03-01");
+        planner
+            .assign(day, Meal::Dinner, names(&["红烧肉"]), false)
+            .await
+            .unwrap();
+        planner
+            .assign(day, Meal::Dinner, names(&["红烧肉", "炒青菜"]), true)
+            .await
+            .unwrap();
+        assert_eq!(
+            dishes(&planner, "2024-03-01", Meal::Dinner).await,
+            names(&["红烧肉", "炒青菜"])
+        );
+        planner
+            .assign(day, Meal::Dinner, names(&["番茄蛋汤"]), false)
+            .await
+            .unwrap();
+        assert_eq!(
+            dishes(&planner, "2024-03-01", Meal::Dinner).await,
+            names(&["番茄蛋汤"])
+        );
+    }
+
+    #[tokio::test]
+    async fn moving_appends_to_the_target_and_empties_the_source() {
+        let planner = planner();
+        let (from, to) = (date("2024-03-01"), date("2024-03-02"));
+        planner
+            .assign(from, Meal::Lunch, names(&["红烧肉", "炒青菜"]), false)
+            .await
+            .unwrap();
+        planner
+            .assign(to, Meal::Dinner, names(&["炒青菜", "番茄蛋汤"]), false)
+            .await
+            .unwrap();
+        planner
+            .move_slot((from, Meal::Lunch), (to, Meal::Dinner))
+            .await
+            .unwrap();
+        assert!(dishes(&planner, "2024-03-01", Meal::Lunch).await.is_empty());
+        assert_eq!(
+            dishes(&planner, "2024-03-02", Meal::Dinner).await,
+            names(&["炒青菜", "番茄蛋汤", "红烧肉"])
+        );
+    }
+
+    #[tokio::test]
+    async fn moving_a_slot_onto_itself_keeps_it() {
+        let planner = planner();
+        let day = date("2024-03-01");
+        planner
+            .assign(day, Meal::Lunch, names(&["红烧肉"]), false)
+            .await
+            .unwrap();
+        planner
+            .move_slot((day, Meal::Lunch), (day, Meal::Lunch))
+            .await
+            .unwrap();
+        assert_eq!(
+            dishes(&planner, "2024-03-01", Meal::Lunch).await,
+            names(&["红烧肉"])
+        );
+    }
+}
@@ -0,0 +1,508 @@
+use std::{
+    collections::{btree_map, BTreeMap, HashMap},
+    fs::File,
+    io::Read,
+    ops::Bound,
+    path::Path,
+    sync::RwLock,
+};
+
+use anyhow::Error;
+use async_graphql::OutputType;
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use super::store::{
+    check_rename, named_in, rename_in, BatchMode, CookLogStore, DishPatch, DishStore,
+    IngredientStore, MealPlanStore, PantryStore, PhotoStore, ShareStore, UserRecord, UserStore,
+    WriteCondition,
+};
+use crate::graphql::{
+    CookEntry, Dish, DishFilter, Ingredient, Meal, MealSlot, Page, PantryItem, ShareableMenu,
+};
+
+/// In-process implementation of every store in `data_sources::store`.
+/// Nothing is persisted, the tables live as long as the process.
+#[derive(Default)]
+pub(crate) struct MemoryStore {
+    dishes: RwLock<BTreeMap<String, Dish>>,
+    ingredients: RwLock<BTreeMap<String, Ingredient>>,
+    shares: RwLock<BTreeMap<String, ShareableMenu>>,
+    meal_plans: RwLock<BTreeMap<(NaiveDate, Meal), MealSlot>>,
+    cook_log: RwLock<BTreeMap<(String, NaiveDate, Meal), CookEntry>>,
+    pantry: RwLock<BTreeMap<String, PantryItem>>,
+    users: RwLock<BTreeMap<String, UserRecord>>,
+    photos: RwLock<HashMap<String, (String, Vec<u8>)>>,
+}
+
+/// Initial table contents, loaded from a JSON seed file.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Seed {
+    dishes: Vec<Dish>,
+    ingredients: Vec<Ingredient>,
+    shares: Vec<ShareableMenu>,
+    meal_plans: Vec<MealSlot>,
+    cook_log: Vec<CookEntry>,
+    pantry: Vec<PantryItem>,
+    users: Vec<UserRecord>,
+}
+
+impl MemoryStore {
+    pub(crate) fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+    pub(crate) fn from_seed_file(path: impl AsRef<Path>) -> Result<MemoryStore, Error> {
+        let seed: Seed = serde_json::from_reader(File::open(path)?)?;
+        Ok(MemoryStore {
+            dishes: RwLock::new(
+                seed.dishes
+                    .into_iter()
+                    .map(|d| (d.name.clone(), d))
+                    .collect(),
+            ),
+            ingredients: RwLock::new(
+                seed.ingredients
+                    .into_iter()
+                    .map(|i| (i.name.clone(), i))
+                    .collect(),
+            ),
+            shares: RwLock::new(
+                seed.shares
+                    .into_iter()
+                    .map(|s| (s.key.clone(), s))
+                    .collect(),
+            ),
+            meal_plans: RwLock::new(
+                seed.meal_plans
+                    .into_iter()
+                    .map(|s| ((s.date, s.meal), s))
+                    .collect(),
+            ),
+            cook_log: RwLock::new(
+                seed.cook_log
+                    .into_iter()
+                    .map(|e| ((e.dish_name.clone(), e.date, e.meal), e))
+                    .collect(),
+            ),
+            pantry: RwLock::new(
+                seed.pantry
+                    .into_iter()
+                    .map(|p| (p.ingredient_name.clone(), p))
+                    .collect(),
+            ),
+            users: RwLock::new(
+                seed.users
+                    .into_iter()
+                    .map(|u| (u.user_id.clone(), u))
+                    .collect(),
+            ),
+            photos: RwLock::default(),
+        })
+    }
+}
+
+fn get_many<T: Clone>(table: &RwLock<BTreeMap<String, T>>, keys: &[String]) -> Vec<T> {
+    let table = table.read().expect("poisoned lock");
+    keys.iter().filter_map(|k| table.get(k).cloned()).collect()
+}
+
+fn scan_page<T: Clone + OutputType>(
+    table: &RwLock<BTreeMap<String, T>>,
+    filter: impl Fn(&T) -> bool,
+    after: Option<String>,
+    limit: Option<usize>,
+) -> Page<T> {
+    let table = table.read().expect("poisoned lock");
+    let mut rest = match after {
+        Some(after) => table.range((Bound::Excluded(after), Bound::Unbounded)),
+        None => table.range::<String, _>(..),
+    }
+    .filter(|(_, item)| filter(item))
+    .peekable();
+    let mut items = vec![];
+    let mut last = None;
+    while let Some((key, item)) = rest.next_if(|_| limit.is_none_or(|l| items.len() < l)) {
+        items.push(item.clone());
+        last = Some(key.clone());
+    }
+    Page {
+        items,
+        next_cursor: rest.peek().and(last),
+    }
+}
+
+fn delete_many<T>(table: &RwLock<BTreeMap<String, T>>, keys: &[String]) {
+    let mut table = table.write().expect("poisoned lock");
+    for key in keys {
+        table.remove(key);
+    }
+}
+
+fn insert_absent(ingredients: &mut BTreeMap<String, Ingredient>, items: Vec<Ingredient>) {
+    for item in items {
+        ingredients.entry(item.name.clone()).or_insert(item);
+    }
+}
+
+fn scan<T: Clone>(table: &RwLock<BTreeMap<String, T>>) -> Vec<T> {
+    table
+        .read()
+        .expect("poisoned lock")
+        .values()
+        .cloned()
+        .collect()
+}
+
+/// A dish without `photo` keeps its stored photo.
+fn upsert_dish(dishes: &mut BTreeMap<String, Dish>, item: &Dish) {
+    let mut item = item.clone();
+    if item.photo.is_none() {
+        item.photo = dishes.get(&item.name).and_then(|d| d.photo.clone());
+    }
+    dishes.insert(item.name.clone(), item);
+}
+
+#[async_trait::async_trait]
+impl DishStore for MemoryStore {
+    async fn get_many(&self, names: &[String]) -> Result<Vec<Dish>, Error> {
+        Ok(get_many(&self.dishes, names))
+    }
+    async fn scan_page(
+        &self,
+        filter: Option<&DishFilter>,
+        after: Option<String>,
+        limit: Option<usize>,
+    ) -> Result<Page<Dish>, Error> {
+        Ok(scan_page(
+            &self.dishes,
+            |dish| filter.is_none_or(|f| f.matches(dish)),
+            after,
+            limit,
+        ))
+    }
+    async fn scan(&self, filter: Option<&DishFilter>) -> Result<Vec<Dish>, Error> {
+        let mut dishes = scan(&self.dishes);
+        if let Some(filter) = filter {
+            dishes.retain(|dish| filter.matches(dish));
+        }
+        Ok(dishes)
+    }
+    async fn count(&self, filter: Option<&DishFilter>) -> Result<usize, Error> {
+        let dishes = self.dishes.read().expect("poisoned lock");
+        Ok(match filter {
+            Some(filter) => dishes.values().filter(|dish| filter.matches(dish)).count(),
+            None => dishes.len(),
+        })
+    }
+    async fn migrate_quantities(&self) -> Result<usize, Error> {
+        // Recipes are parsed when they are loaded
+        Ok(0)
+    }
+    async fn update_many_if(
+        &self,
+        items: &[Dish],
+        condition: WriteCondition,
+        mode: BatchMode,
+        new_ingredients: &[Ingredient],
+    ) -> Result<Vec<bool>, Error> {
+        let mut dishes = self.dishes.write().expect("poisoned lock");
+        let mut ingredients = self.ingredients.write().expect("poisoned lock");
+        let held: Vec<bool> = items
+            .iter()
+            .map(|item| condition.holds(dishes.contains_key(&item.name)))
+            .collect();
+        if mode.writes(&held) {
+            let written: Vec<&Dish> = items
+                .iter()
+                .zip(&held)
+                .filter(|(_, held)| **held)
+                .map(|(item, _)| item)
+                .collect();
+            let recipes = written.iter().map(|item| item.ingredients.as_slice());
+            insert_absent(&mut ingredients, named_in(new_ingredients, recipes));
+            for item in written {
+                upsert_dish(&mut dishes, item);
+            }
+        }
+        Ok(held)
+    }
+    async fn patch_many(
+        &self,
+        patches: &[DishPatch],
+        mode: BatchMode,
+        new_ingredients: &[Ingredient],
+    ) -> Result<Vec<bool>, Error> {
+        let mut dishes = self.dishes.write().expect("poisoned lock");
+        let mut ingredients = self.ingredients.write().expect("poisoned lock");
+        let found: Vec<bool> = patches
+            .iter()
+            .map(|patch| dishes.contains_key(&patch.name))
+            .collect();
+        if mode.writes(&found) {
+            let written: Vec<&DishPatch> = patches
+                .iter()
+                .zip(&found)
+                .filter(|(_, found)| **found)
+                .map(|(patch, _)| patch)
+                .collect();
+            let recipes = written
+                .iter()
+                .filter_map(|patch| patch.ingredients.as_deref());
+            insert_absent(&mut ingredients, named_in(new_ingredients, recipes));
+            for patch in written {
+                if let Some(dish) = dishes.get_mut(&patch.name) {
+                    patch.apply(dish);
+                }
+            }
+        }
+        Ok(found)
+    }
+    async fn rename(&self, from: &str, to: &str) -> Result<(), Error> {
+        // Tables are always locked in the order they are declared in
+        let mut dishes = self.dishes.write().expect("poisoned lock");
+        let mut meal_plans = self.meal_plans.write().expect("poisoned lock");
+        let mut cook_log = self.cook_log.write().expect("poisoned lock");
+        check_rename(
+            "dish",
+            from,
+            to,
+            dishes.contains_key(from),
+            dishes.contains_key(to),
+        )?;
+        let mut dish = dishes.remove(from).expect("dish to rename");
+        dish.name = to.to_string();
+        dishes.insert(dish.name.clone(), dish);
+        for slot in meal_plans.values_mut() {
+            rename_in(&mut slot.dish_names, from, to);
+        }
+        let moved: Vec<_> = cook_log
+            .keys()
+            .filter(|(dish_name, _, _)| dish_name == from)
+            .cloned()
+            .collect();
+        for key in moved {
+            let mut entry = cook_log.remove(&key).expect("cook log entry");
+            entry.dish_name = to.to_string();
+            cook_log.insert((entry.dish_name.clone(), entry.date, entry.meal), entry);
+        }
+        Ok(())
+    }
+    async fn delete_many(&self, names: &[String]) -> Result<(), Error> {
+        delete_many(&self.dishes, names);
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl IngredientStore for MemoryStore {
+    async fn get_many(&self, names: &[String]) -> Result<Vec<Ingredient>, Error> {
+        Ok(get_many(&self.ingredients, names))
+    }
+    async fn scan_page(
+        &self,
+        after: Option<String>,
+        limit: Option<usize>,
+    ) -> Result<Page<Ingredient>, Error> {
+        Ok(scan_page(&self.ingredients, |_| true, after, limit))
+    }
+    async fn scan(&self) -> Result<Vec<Ingredient>, Error> {
+        Ok(scan(&self.ingredients))
+    }
+    async fn count(&self) -> Result<usize, Error> {
+        Ok(self.ingredients.read().expect("poisoned lock").len())
+    }
+    async fn update_many_if(
+        &self,
+        items: &[Ingredient],
+        condition: WriteCondition,
+        mode: BatchMode,
+    ) -> Result<Vec<bool>, Error> {
+        let mut ingredients = self.ingredients.write().expect("poisoned lock");
+        let held: Vec<bool> = items
+            .iter()
+            .map(|item| condition.holds(ingredients.contains_key(&item.name)))
+            .collect();
+        if mode.writes(&held) {
+            for (item, _) in items.iter().zip(&held).filter(|(_, held)| **held) {
+                ingredients.insert(item.name.clone(), item.clone());
+            }
+        }
+        Ok(held)
+    }
+    async fn rename(&self, from: &str, to: &str) -> Result<Vec<Dish>, Error> {
+        // Tables are always locked in the order they are declared in
+        let mut dishes = self.dishes.write().expect("poisoned lock");
+        let mut ingredients = self.ingredients.write().expect("poisoned lock");
+        let mut pantry = self.pantry.write().expect("poisoned lock");
+        check_rename(
+            "ingredient",
+            from,
+            to,
+            ingredients.contains_key(from),
+            ingredients.contains_key(to),
+        )?;
+        let mut ingredient = ingredients.remove(from).expect("ingredient to rename");
+        ingredient.name = to.to_string();
+        ingredients.insert(ingredient.name.clone(), ingredient);
+        if let Some(mut item) = pantry.remove(from) {
+            item.ingredient_name = to.to_string();
+            pantry.insert(item.ingredient_name.clone(), item);
+        }
+        let mut rewritten = vec![];
+        for dish in dishes.values_mut() {
+            let mut changed = false;
+            for line in dish.ingredients.iter_mut().filter(|line| line.name == from) {
+                line.name = to.to_string();
+                changed = true;
+            }
+            if changed {
+                rewritten.push(dish.clone());
+            }
+        }
+        Ok(rewritten)
+    }
+    async fn delete_many(&self, names: &[String]) -> Result<Vec<Dish>, Error> {
+        let mut dishes = self.dishes.write().expect("poisoned lock");
+        let mut ingredients = self.ingredients.write().expect("poisoned lock");
+        for name in names {
+            ingredients.remove(name);
+        }
+        let mut rewritten = vec![];
+        for dish in dishes.values_mut() {
+            let before = dish.ingredients.len();
+            dish.ingredients.retain(|line| !names.contains(&line.name));
+            if dish.ingredients.len() < before {
+                rewritten.push(dish.clone());
+            }
+        }
+        Ok(rewritten)
+    }
+}
+
+#[async_trait::async_trait]
+impl ShareStore for MemoryStore {
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<ShareableMenu>, Error> {
+        Ok(get_many(&self.shares, keys))
+    }
+    async fn put(&self, item: &ShareableMenu) -> Result<(), Error> {
+        self.shares
+            .write()
+            .expect("poisoned lock")
+            .insert(item.key.clone(), item.clone());
+        Ok(())
+    }
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.shares.write().expect("poisoned lock").remove(key);
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl MealPlanStore for MemoryStore {
+    async fn get_range(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<MealSlot>, Error> {
+        Ok(self
+            .meal_plans
+            .read()
+            .expect("poisoned lock")
+            .range((from, Meal::Lunch)..=(to, Meal::Dinner))
+            .map(|(_, slot)| slot.clone())
+            .collect())
+    }
+    async fn put_many(&self, slots: &[MealSlot]) -> Result<(), Error> {
+        let mut table = self.meal_plans.write().expect("poisoned lock");
+        for slot in slots {
+            match slot.dish_names.is_empty() {
+                true => table.remove(&(slot.date, slot.meal)),
+                false => table.insert((slot.date, slot.meal), slot.clone()),
+            };
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl CookLogStore for MemoryStore {
+    async fn get_for_dishes(&self, dish_names: &[String]) -> Result<Vec<CookEntry>, Error> {
+        Ok(self
+            .cook_log
+            .read()
+            .expect("poisoned lock")
+            .values()
+            .filter(|entry| dish_names.contains(&entry.dish_name))
+            .cloned()
+            .collect())
+    }
+    async fn get_range(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<CookEntry>, Error> {
+        Ok(self
+            .cook_log
+            .read()
+            .expect("poisoned lock")
+            .values()
+            .filter(|entry| from <= entry.date && entry.date <= to)
+            .cloned()
+            .collect())
+    }
+    async fn put_many(&self, entries: &[CookEntry]) -> Result<Vec<CookEntry>, Error> {
+        let mut table = self.cook_log.write().expect("poisoned lock");
+        let mut added = vec![];
+        for entry in entries {
+            let key = (entry.dish_name.clone(), entry.date, entry.meal);
+            if let btree_map::Entry::Vacant(vacant) = table.entry(key) {
+                vacant.insert(entry.clone());
+                added.push(entry.clone());
+            }
+        }
+        Ok(added)
+    }
+}
+
+#[async_trait::async_trait]
+impl PantryStore for MemoryStore {
+    async fn get_many(&self, ingredient_names: &[String]) -> Result<Vec<PantryItem>, Error> {
+        Ok(get_many(&self.pantry, ingredient_names))
+    }
+    async fn scan(&self) -> Result<Vec<PantryItem>, Error> {
+        Ok(scan(&self.pantry))
+    }
+    async fn put_many(&self, items: &[PantryItem]) -> Result<(), Error> {
+        let mut pantry = self.pantry.write().expect("poisoned lock");
+        for item in items {
+            pantry.insert(item.ingredient_name.clone(), item.clone());
+        }
+        Ok(())
+    }
+    async fn delete_many(&self, ingredient_names: &[String]) -> Result<(), Error> {
+        delete_many(&self.pantry, ingredient_names);
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl UserStore for MemoryStore {
+    async fn get(&self, user_id: &str) -> Result<Option<UserRecord>, Error> {
+        Ok(self
+            .users
+            .read()
+            .expect("poisoned lock")
+            .get(user_id)
+            .cloned())
+    }
+}
+
+#[async_trait::async_trait]
+impl PhotoStore for MemoryStore {
+    async fn put(&self, key: &str, content_type: &str, mut content: File) -> Result<(), Error> {
+        let mut bytes = vec![];
+        content.read_to_end(&mut bytes)?;
+        self.photos
+            .write()
+            .expect("poisoned lock")
+            .insert(key.to_owned(), (content_type.to_owned(), bytes));
+        Ok(())
+    }
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.photos.write().expect("poisoned lock").remove(key);
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+use std::future::Future;
+
+use anyhow::{anyhow, Error};
+use async_graphql::OutputType;
+
+use crate::graphql::Page;
+
+/// A slice of a table selected with Relay style `after`/`before`/`first`/`last`.
+pub(crate) struct Window<T> {
+    pub items: Vec<T>,
+    pub has_previous_page: bool,
+    pub has_next_page: bool,
+}
+
+/// Walks a table in scan order from `after` up to `before`, both exclusive,
+/// then keeps the `first` and afterwards the `last` items of what it found.
+/// Cursors are item keys, so `after` is handed to the store as its
+/// continuation key, while `before` has to be found by scanning.
+pub(crate) async fn walk<T, F, Fut>(
+    mut fetch: F,
+    key: impl Fn(&T) -> &str,
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<usize>,
+    last: Option<usize>,
+) -> Result<Window<T>, Error>
+where
+    T: OutputType,
+    F: FnMut(Option<String>, Option<usize>) -> Fut,
+    Fut: Future<Output = Result<Page<T>, Error>>,
+{
+    let has_previous_page = after.is_some();
+    // Without `before` or `last` one item past `first` is enough to know
+    // whether a next page exists.
+    let wanted = match (&before, last, first) {
+        (None, None, Some(first)) => Some(first + 1),
+        _ => None,
+    };
+    let mut items = vec![];
+    let mut has_next_page = false;
+    let mut cursor = after;
+    'pages: loop {
+        let page = fetch(cursor, wanted.map(|w| w - items.len())).await?;
+        for item in page.items {
+            if before.as_deref() == Some(key(&item)) {
+                has_next_page = true;
+                break 'pages;
+            }
+            items.push(item);
+            if wanted == Some(items.len()) {
+                break 'pages;
+            }
+        }
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+
+    Ok(Window {
+        items,
+        has_previous_page,
+        has_next_page,
+    }
+    .trim(first, last))
+}
+
+/// Like `walk`, over items already loaded and sorted. A cursor that is
+/// not among them fails rather than starting over, as the item it names
+/// may have been renamed or deleted since the previous page.
+pub(crate) fn slice<T>(
+    mut items: Vec<T>,
+    key: impl Fn(&T) -> &str,
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<usize>,
+    last: Option<usize>,
+) -> Result<Window<T>, Error> {
+    let position = |cursor: &str| {
+        items
+            .iter()
+            .position(|item| key(item) == cursor)
+            .ok_or_else(|| anyhow!("unknown cursor {}", cursor))
+    };
+    let end = before.map_or(Ok(items.len()), |b| position(&b))?;
+    let start = after.map_or(Ok(0), |a| position(&a).map(|i| i + 1))?;
+    let has_next_page = end < items.len();
+    let has_previous_page = start > 0;
+    items.truncate(end);
+    items.drain(..start.min(end));
+    Ok(Window {
+        items,
+        has_previous_page,
+        has_next_page,
+    }
+    .trim(first, last))
+}
+
+impl<T> Window<T> {
+    /// Keeps the `first` and afterwards the `last` items.
+    fn trim(mut self, first: Option<usize>, last: Option<usize>) -> Window<T> {
+        if let Some(first) = first {
+            if self.items.len() > first {
+                self.items.truncate(first);
+                self.has_next_page = true;
+            }
+        }
+        if let Some(last) = last {
+            if self.items.len() > last {
+                self.items.drain(..self.items.len() - last);
+                self.has_previous_page = true;
+            }
+        }
+        self
+    }
+}
+
+impl<T: OutputType> Window<T> {
+    /// A page continuing after the last item of the window.
+    pub(crate) fn into_page(self, key: impl Fn(&T) -> &str) -> Page<T> {
+        let next_cursor = match self.has_next_page {
+            true => self.items.last().map(|item| key(item).to_string()),
+            false => None,
+        };
+        Page {
+            items: self.items,
+            next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphql::Ingredient;
+
+    fn ingredients(names: &[&str]) -> Vec<Ingredient> {
+        names
+            .iter()
+            .map(|name| Ingredient {
+                name: name.to_string(),
+                category: String::new(),
+                density: None,
+                piece_weight: None,
+            })
+            .collect()
+    }
+
+    fn names(window: &Window<Ingredient>) -> Vec<&str> {
+        window.items.iter().map(|i| i.name.as_str()).collect()
+    }
+
+    /// Walks `table` served two items per page at most, like a store with
+    /// a small page size would.
+    async fn walk_table(
+        table: &[Ingredient],
+        after: Option<&str>,
+        before: Option<&str>,
+        first: Option<usize>,
+        last: Option<usize>,
+    ) -> Window<Ingredient> {
+        let fetch = |cursor: Option<String>, limit: Option<usize>| {
+            let start = cursor.map_or(0, |c| table.iter().position(|i| i.name == c).unwrap() + 1);
+            let end = (start + limit.unwrap_or(2).min(2)).min(table.len());
+            let items = table[start..end].to_vec();
+            let next_cursor = (end < table.len()).then(|| table[end - 1].name.clone());
+            async move { Ok(Page { items, next_cursor }) }
+        };
+        walk(
+            fetch,
+            |i: &Ingredient| &i.name,
+            after.map(str::to_string),
+            before.map(str::to_string),
+            first,
+            last,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn walk_takes_first_items_across_pages() {
+        let table = ingredients(&["a", "b", "c", "d", "e"]);
+        let window = walk_table(&table, None, None, Some(3), None).await;
+        assert_eq!(names(&window), ["a", "b", "c"]);
+        assert!(window.has_next_page);
+        assert!(!window.has_previous_page);
+    }
+
+    #[tokio::test]
+    async fn walk_starts_after_and_stops_before() {
+        let table = ingredients(&["a", "b", "c", "d", "e"]);
+        let window = walk_table(&table, Some("a"), Some("e"), None, None).await;
+        assert_eq!(names(&window), ["b", "c", "d"]);
+        assert!(window.has_previous_page);
+        assert!(window.has_next_page);
+    }
+
+    #[tokio::test]
+    async fn walk_takes_last_items() {
+        let table = ingredients(&["a", "b", "c", "d", "e"]);
+        let window = walk_table(&table, None, Some("e"), None, Some(2)).await;
+        assert_eq!(names(&window), ["c", "d"]);
+        assert!(window.has_previous_page);
+    }
+
+    #[tokio::test]
+    async fn walk_to_the_end_has_no_next_page() {
+        let table = ingredients(&["a", "b", "c"]);
+        let window = walk_table(&table, Some("a"), None, Some(5), None).await;
+        assert_eq!(names(&window), ["b", "c"]);
+        assert!(!window.has_next_page);
+        assert_eq!(window.into_page(|i| &i.name).next_cursor, None);
+    }
+
+    fn slice_names(
+        after: Option<&str>,
+        before: Option<&str>,
+        first: Option<usize>,
+        last: Option<usize>,
+    ) -> (Vec<String>, bool, bool) {
+        let items: Vec<String> = ["a", "b", "c", "d", "e"].map(String::from).to_vec();
+        let window = slice(
+            items,
+            |s: &String| s,
+            after.map(str::to_string),
+            before.map(str::to_string),
+            first,
+            last,
+        )
+        .unwrap();
+        (window.items, window.has_previous_page, window.has_next_page)
+    }
+
+    #[test]
+    fn slice_matches_walk() {
+        assert_eq!(
+            slice_names(None, None, Some(2), None),
+            (vec!["a".to_string(), "b".to_string()], false, true)
+        );
+        assert_eq!(
+            slice_names(Some("a"), Some("e"), None, Some(2)),
+            (vec!["c".to_string(), "d".to_string()], true, true)
+        );
+        assert_eq!(
+            slice_names(Some("d"), None, None, None),
+            (vec!["e".to_string()], true, false)
+        );
+    }
+
+    #[test]
+    fn slice_between_crossed_cursors_is_empty() {
+        let (items, _, _) = slice_names(Some("d"), Some("b"), None, None);
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn slice_rejects_unknown_cursors() {
+        for (after, before) in [(Some("x"), None), (None, Some("x"))] {
+            let items: Vec<String> = ["a", "b"].map(String::from).to_vec();
+            let result = slice(
+                items,
+                |s: &String| s,
+                after.map(str::to_string),
+                before.map(str::to_string),
+                None,
+                None,
+            );
+            assert_eq!(result.err().unwrap().to_string(), "unknown cursor x");
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{anyhow, Error};
+use chrono::NaiveDate;
+
+use super::store::PantryStore;
+use crate::{
+    graphql::{Ingredient, PantryItem, RawRecipeIngredient},
+    quantity::convert_amount,
+};
+
+/// What a deduction took from stock and what it could not.
+pub(crate) struct Deduction {
+    /// Ingredients whose stock went down
+    pub deducted: Vec<String>,
+    /// Ingredients in stock in a unit the recipe's quantity does not
+    /// convert to
+    pub unconverted: Vec<String>,
+}
+
+/// Reads and adjusts the stock of ingredients in the kitchen.
+pub(crate) struct Pantry {
+    store: Arc<dyn PantryStore>,
+}
+
+impl Pantry {
+    pub(crate) fn new(store: Arc<dyn PantryStore>) -> Pantry {
+        Pantry { store }
+    }
+
+    /// Everything in stock, by ingredient name.
+    pub(crate) async fn load_all(&self) -> Result<Vec<PantryItem>, Error> {
+        let mut items = self.store.scan().await?;
+        items.sort_by(|a, b| a.ingredient_name.cmp(&b.ingredient_name));
+        Ok(items)
+    }
+
+    /// Items left in stock that expire on or before `until`, the soonest
+    /// first.
+    pub(crate) async fn expiring(&self, until: NaiveDate) -> Result<Vec<PantryItem>, Error> {
+        let mut items: Vec<PantryItem> = self
+            .store
+            .scan()
+            .await?
+            .into_iter()
+            .filter(|item| item.quantity > 0.0)
+            .filter(|item| {
+                item.expires_on
+                    .is_some_and(|expires_on| expires_on <= until)
+            })
+            .collect();
+        items.sort_by(|a, b| {
+            a.expires_on
+                .cmp(&b.expires_on)
+                .then_with(|| a.ingredient_name.cmp(&b.ingredient_name))
+        });
+        Ok(items)
+    }
+
+    /// Replaces the stock of every item.
+    pub(crate) async fn set_many(&self, items: &[PantryItem]) -> Result<(), Error> {
+        if let Some(item) = items.iter().find(|item| item.quantity < 0.0) {
+            return Err(anyhow!("negative quantity for {}", item.ingredient_name));
+        }
+        self.store.put_many(items).await
+    }
+
+    /// Adds `delta` in `unit` to the stock of an ingredient, or takes it
+    /// away when negative. Stock never drops below zero. An ingredient not
+    /// in stock yet is added in `unit`. Dates are updated when given.
+    /// The stock is read, changed and written back, so of two adjustments
+    /// of the same ingredient at once one may be lost.
+    pub(crate) async fn adjust(
+        &self,
+        ingredient: &Ingredient,
+        delta: f64,
+        unit: Option<String>,
+        purchased_on: Option<NaiveDate>,
+        expires_on: Option<NaiveDate>,
+    ) -> Result<PantryItem, Error> {
+        let mut item = self
+            .store
+            .get_many(std::slice::from_ref(&ingredient.name))
+            .await?
+            .pop()
+            .unwrap_or_else(|| PantryItem {
+                ingredient_name: ingredient.name.clone(),
+                quantity: 0.0,
+                unit: unit.clone(),
+                purchased_on: None,
+                expires_on: None,
+            });
+        let delta = convert_amount(
+            delta,
+            unit.as_deref(),
+            item.unit.as_deref(),
+            Some(ingredient),
+        )
+        .ok_or_else(|| {
+            anyhow!(
+                "cannot convert {} to {} for {}",
+                unit.as_deref().unwrap_or("pieces"),
+                item.unit.as_deref().unwrap_or("pieces"),
+                ingredient.name
+            )
+        })?;
+        item.quantity = (item.quantity + delta).max(0.0);
+        if purchased_on.is_some() {
+            item.purchased_on = purchased_on;
+        }
+        if expires_on.is_some() {
+            item.expires_on = expires_on;
+        }
+        self.store.put_many(&[item.clone()]).await?;
+        Ok(item)
+    }
+
+    pub(crate) async fn remove_many(&self, ingredient_names: &[String]) -> Result<(), Error> {
+        self.store.delete_many(ingredient_names).await
+    }
+
+    /// Takes what recipe lines need out of stock, each line's amount
+    /// multiplied by its factor. Lines without amount and ingredients not
+    /// in stock are skipped. Like `adjust`, this may lose a change made to
+    /// the same stock at the same time.
+    pub(crate) async fn deduct(
+        &self,
+        lines: &[(&RawRecipeIngredient, f64)],
+        ingredients: &HashMap<String, Ingredient>,
+    ) -> Result<Deduction, Error> {
+        let names: Vec<String> = lines.iter().map(|(line, _)| line.name.clone()).collect();
+        let mut stock: HashMap<String, PantryItem> = self
+            .store
+            .get_many(&names)
+            .await?
+            .into_iter()
+            .map(|item| (item.ingredient_name.clone(), item))
+            .collect();
+        let mut deduction = Deduction {
+            deducted: vec![],
+            unconverted: vec![],
+        };
+        for (line, factor) in lines {
+            let (Some(item), Some(amount)) = (stock.get_mut(&line.name), line.quantity.amount)
+            else {
+                continue;
+            };
+            match convert_amount(
+                amount * factor,
+                line.quantity.unit.as_deref(),
+                item.unit.as_deref(),
+                ingredients.get(&line.name),
+            ) {
+                Some(used) => {
+                    item.quantity = (item.quantity - used).max(0.0);
+                    if !deduction.deducted.contains(&line.name) {
+                        deduction.deducted.push(line.name.clone());
+                    }
+                }
+                None => deduction.unconverted.push(line.name.clone()),
+            }
+        }
+        let changed: Vec<PantryItem> = deduction
+            .deducted
+            .iter()
+            .filter_map(|name| stock.remove(name))
+            .collect();
+        self.store.put_many(&changed).await?;
+        Ok(deduction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data_sources::memory::MemoryStore, graphql::Quantity};
+
+    fn ingredient(name: &str, piece_weight: Option<f64>) -> Ingredient {
+        Ingredient {
+            name: name.to_string(),
+            category: "未分类".to_string(),
+            density: None,
+            piece_weight,
+        }
+    }
+
+    fn stock(name: &str, quantity: f64, unit: Option<&str>) -> PantryItem {
+        PantryItem {
+            ingredient_name: name.to_string(),
+            quantity,
+            unit: unit.map(str::to_string),
+            purchased_on: None,
+            expires_on: None,
+        }
+    }
+
+    async fn pantry(items: &[PantryItem]) -> Pantry {
+        let pantry = Pantry::new(Arc::new(MemoryStore::new()));
+        pantry.set_many(items).await.unwrap();
+        pantry
+    }
+
+    async fn quantity(pantry: &Pantry, name: &str) -> f64 {
+        let items = pantry.load_all().await.unwrap();
+        let item = items.iter().find(|item| item.ingredient_name == name);
+        item.unwrap().quantity
+    }
+
+    fn date(text: &str) -> Option<NaiveDate> {
+        Some(text.parse().unwrap())
+    }
+
+    #[tokio::test]
+    async fn adjusting_missing_stock_adds_it_in_the_unit_given() {
+        let pantry = pantry(&[]).await;
+        let salt = ingredient("盐", None);
+        let item = pantry
+            .adjust(&salt, 500.0, Some("g".into()), None, date("2030-01-01"))
+            .await
+            .unwrap();
+        assert_eq!((item.quantity, item.unit.as_deref()), (500.0, Some("g")));
+        assert_eq!(item.expires_on, date("2030-01-01"));
+    }
+
+    #[tokio::test]
+    async fn adjustments_convert_to_the_stock_unit() {
+        let pantry = pantry(&[stock("鸡蛋", 1.0, Some("kg"))]).await;
+        let egg = ingredient("鸡蛋", Some(50.0));
+        let item = pantry
+            .adjust(&egg, -200.0, Some("g".into()), None, None)
+            .await
+            .unwrap();
+        assert!((item.quantity - 0.8).abs() < 1e-9);
+        let item = pantry
+            .adjust(&egg, 4.0, Some("个".into()), None, None)
+            .await
+            .unwrap();
+        assert!((item.quantity - 1.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn adjustments_never_take_stock_below_zero() {
+        let pantry = pantry(&[stock("盐", 100.0, Some("g"))]).await;
+        let salt = ingredient("盐", None);
+        let item = pantry
+            .adjust(&salt, -1.0, Some("kg".into()), None, None)
+            .await
+            .unwrap();
+        assert_eq!(item.quantity, 0.0);
+    }
+
+    #[tokio::test]
+    async fn adjustments_in_unconvertible_units_fail() {
+        let pantry = pantry(&[stock("盐", 100.0, Some("g"))]).await;
+        let salt = ingredient("盐", None);
+        let result = pantry.adjust(&salt, 2.0, None, None, None).await;
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "cannot convert pieces to g for 盐"
+        );
+        assert_eq!(quantity(&pantry, "盐").await, 100.0);
+    }
+
+    #[tokio::test]
+    async fn deductions_convert_clamp_and_report_what_they_cannot_convert() {
+        let pantry = pantry(&[
+            stock("猪肉", 1.0, Some("kg")),
+            stock("鸡蛋", 3.0, Some("个")),
+            stock("牛奶", 1.0, Some("l")),
+        ])
+        .await;
+        let ingredients = HashMap::from([
+            ("猪肉".to_string(), ingredient("猪肉", None)),
+            ("鸡蛋".to_string(), ingredient("鸡蛋", None)),
+        ]);
+        let line = |name: &str, quantity: &str| RawRecipeIngredient {
+            name: name.to_string(),
+            quantity: Quantity::parse(quantity),
+        };
+        let pork = line("猪肉", "300g");
+        let pork_again = line("猪肉", "100g");
+        let eggs = line("鸡蛋", "2个");
+        let milk = line("牛奶", "2个");
+        let salt = line("盐", "5g");
+        let onion = line("葱", "适量");
+        let lines = [
+            (&pork, 2.0),
+            (&pork_again, 1.0),
+            (&eggs, 2.0),
+            (&milk, 1.0),
+            (&salt, 1.0),
+            (&onion, 1.0),
+        ];
+        let deduction = pantry.deduct(&lines, &ingredients).await.unwrap();
+        assert_eq!(deduction.deducted, ["猪肉", "鸡蛋"]);
+        assert_eq!(deduction.unconverted, ["牛奶"]);
+        assert!((quantity(&pantry, "猪肉").await - 0.3).abs() < 1e-9);
+        assert_eq!(quantity(&pantry, "鸡蛋").await, 0.0);
+        assert_eq!(quantity(&pantry, "牛奶").await, 1.0);
+    }
+}
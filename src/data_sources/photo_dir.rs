@@ -0,0 +1,34 @@
+use std::{fs::File, io::ErrorKind, path::PathBuf};
+
+use anyhow::Error;
+
+use super::store::PhotoStore;
+
+/// Keeps uploaded photos as files in a local directory, for hosts without S3.
+pub(crate) struct DirPhotoStore {
+    dir: PathBuf,
+}
+
+impl DirPhotoStore {
+    pub(crate) fn new(dir: impl Into<PathBuf>) -> Result<DirPhotoStore, Error> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(DirPhotoStore { dir })
+    }
+}
+
+#[async_trait::async_trait]
+impl PhotoStore for DirPhotoStore {
+    async fn put(&self, key: &str, _content_type: &str, content: File) -> Result<(), Error> {
+        let mut content = tokio::fs::File::from_std(content);
+        let mut file = tokio::fs::File::create(self.dir.join(key)).await?;
+        tokio::io::copy(&mut content, &mut file).await?;
+        Ok(())
+    }
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        match tokio::fs::remove_file(self.dir.join(key)).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
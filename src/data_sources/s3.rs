@@ -0,0 +1,42 @@
+use std::fs::File;
+
+use anyhow::Error;
+use aws_sdk_s3::{types::ByteStream, Client};
+
+use super::store::PhotoStore;
+
+const S3_BUCKET: &str = "todays-menu-photos";
+
+pub(crate) struct S3PhotoStore {
+    s3_client: Client,
+}
+
+impl S3PhotoStore {
+    pub(crate) fn new(s3: Client) -> S3PhotoStore {
+        S3PhotoStore { s3_client: s3 }
+    }
+}
+
+#[async_trait::async_trait]
+impl PhotoStore for S3PhotoStore {
+    async fn put(&self, key: &str, content_type: &str, content: File) -> Result<(), Error> {
+        self.s3_client
+            .put_object()
+            .bucket(S3_BUCKET)
+            .key(key)
+            .content_type(content_type)
+            .body(ByteStream::from_file(tokio::fs::File::from_std(content)).await?)
+            .send()
+            .await?;
+        Ok(())
+    }
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.s3_client
+            .delete_object()
+            .bucket(S3_BUCKET)
+            .key(key)
+            .send()
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,440 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::Error;
+use tokio::sync::Mutex;
+
+use super::store::{DishStore, IngredientStore};
+use crate::graphql::{Dish, Ingredient};
+
+/// Other instances write to the same tables, so the index is rebuilt from
+/// the stores once it is older than this.
+const MAX_AGE: Duration = Duration::from_secs(300);
+
+/// Hits scoring below this share of a perfect match are dropped.
+const MIN_SCORE: f32 = 0.3;
+
+const NAME_WEIGHT: f32 = 3.0;
+const TAG_WEIGHT: f32 = 1.5;
+const INGREDIENT_WEIGHT: f32 = 1.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum DocKind {
+    Dish,
+    Ingredient,
+}
+
+pub(crate) struct SearchHit {
+    pub kind: DocKind,
+    pub name: String,
+    pub score: f32,
+}
+
+/// A dish that can be cooked, at least in part, from what is available.
+pub(crate) struct CookableHit {
+    pub name: String,
+    /// Share of the recipe's ingredients that are available
+    pub coverage: f32,
+    pub matched: Vec<String>,
+    pub missing: Vec<String>,
+}
+
+type DocKey = (DocKind, String);
+
+#[derive(Default)]
+struct IndexState {
+    /// Terms of every document with the weight of the best field holding them
+    docs: HashMap<DocKey, HashMap<String, f32>>,
+    postings: HashMap<String, HashSet<DocKey>>,
+    /// Ingredient names of every dish's recipe, in recipe order
+    recipes: HashMap<String, Vec<String>>,
+    /// Dishes using each ingredient, the inverse of `recipes`
+    used_in: HashMap<String, HashSet<String>>,
+}
+
+/// In-memory full-text index over dish and ingredient records, and an
+/// inverted index from ingredients to the dishes using them. It is built
+/// from the stores on first use and kept current by the loaders' writes.
+pub(crate) struct SearchIndex {
+    dishes: Arc<dyn DishStore>,
+    ingredients: Arc<dyn IngredientStore>,
+    state: RwLock<Option<(Instant, IndexState)>>,
+    building: Mutex<()>,
+}
+
+impl SearchIndex {
+    pub(crate) fn new(
+        dishes: Arc<dyn DishStore>,
+        ingredients: Arc<dyn IngredientStore>,
+    ) -> SearchIndex {
+        SearchIndex {
+            dishes,
+            ingredients,
+            state: RwLock::new(None),
+            building: Mutex::new(()),
+        }
+    }
+
+    pub(crate) async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, Error> {
+        self.ensure_fresh().await?;
+        let state = self.state.read().expect("poisoned lock");
+        let state = match state.as_ref() {
+            Some((_, state)) => state,
+            None => return Ok(vec![]),
+        };
+        let terms = terms(query);
+        let best: f32 = terms.values().map(|w| w * NAME_WEIGHT).sum();
+        if best == 0.0 {
+            return Ok(vec![]);
+        }
+        let mut scores: HashMap<&DocKey, f32> = HashMap::new();
+        for (term, weight) in &terms {
+            for key in state.postings.get(term).into_iter().flatten() {
+                *scores.entry(key).or_default() += weight * state.docs[key][term];
+            }
+        }
+        let mut hits: Vec<SearchHit> = scores
+            .into_iter()
+            .map(|((kind, name), score)| SearchHit {
+                kind: *kind,
+                score: score / best,
+                name: name.clone(),
+            })
+            .filter(|hit| hit.score >= MIN_SCORE)
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| a.kind.cmp(&b.kind))
+        });
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    /// Dishes using any of `available`, the best covered first. Dishes
+    /// missing more than `max_missing` ingredients are left out.
+    pub(crate) async fn cookable(
+        &self,
+        available: &[String],
+        max_missing: Option<usize>,
+        limit: usize,
+    ) -> Result<Vec<CookableHit>, Error> {
+        self.ensure_fresh().await?;
+        let state = self.state.read().expect("poisoned lock");
+        let state = match state.as_ref() {
+            Some((_, state)) => state,
+            None => return Ok(vec![]),
+        };
+        let available: HashSet<&str> = available.iter().map(|name| name.trim()).collect();
+        let dishes: HashSet<&String> = available
+            .iter()
+            .filter_map(|name| state.used_in.get(*name))
+            .flatten()
+            .collect();
+        let mut hits: Vec<CookableHit> = dishes
+            .into_iter()
+            .filter_map(|dish| {
+                let recipe = &state.recipes[dish];
+                let (matched, missing): (Vec<String>, Vec<String>) = recipe
+                    .iter()
+                    .cloned()
+                    .partition(|name| available.contains(name.as_str()));
+                if max_missing.is_some_and(|max| missing.len() > max) {
+                    return None;
+                }
+                Some(CookableHit {
+                    name: dish.clone(),
+                    coverage: matched.len() as f32 / recipe.len() as f32,
+                    matched,
+                    missing,
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.coverage
+                .partial_cmp(&a.coverage)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.missing.len().cmp(&b.missing.len()))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    /// Reindexes written dishes, a no-op until the index has been built.
+    pub(crate) fn update_dishes(&self, dishes: &[Dish]) {
+        if let Some((_, state)) = self.state.write().expect("poisoned lock").as_mut() {
+            for dish in dishes {
+                state.insert_dish(dish);
+            }
+        }
+    }
+
+    /// Reindexes written ingredients, a no-op until the index has been built.
+    pub(crate) fn update_ingredients(&self, ingredients: &[Ingredient]) {
+        if let Some((_, state)) = self.state.write().expect("poisoned lock").as_mut() {
+            for ingredient in ingredients {
+                state.insert(
+                    (DocKind::Ingredient, ingredient.name.clone()),
+                    ingredient_terms(ingredient),
+                );
+            }
+        }
+    }
+
+    /// Drops deleted dishes, a no-op until the index has been built.
+    pub(crate) fn remove_dishes(&self, names: &[String]) {
+        if let Some((_, state)) = self.state.write().expect("poisoned lock").as_mut() {
+            for name in names {
+                state.remove_dish(name);
+            }
+        }
+    }
+
+    /// Drops deleted ingredients, a no-op until the index has been built.
+    /// Recipes naming them keep their place in the ingredient-to-dish index.
+    pub(crate) fn remove_ingredients(&self, names: &[String]) {
+        if let Some((_, state)) = self.state.write().expect("poisoned lock").as_mut() {
+            for name in names {
+                state.remove(&(DocKind::Ingredient, name.clone()));
+            }
+        }
+    }
+
+    async fn ensure_fresh(&self) -> Result<(), Error> {
+        let is_fresh = || {
+            matches!(
+                self.state.read().expect("poisoned lock").as_ref(),
+                Some((built, _)) if built.elapsed() < MAX_AGE
+            )
+        };
+        if is_fresh() {
+            return Ok(());
+        }
+        let _building = self.building.lock().await;
+        if is_fresh() {
+            return Ok(());
+        }
+        let built = Instant::now();
+        let mut state = IndexState::default();
+        for dish in self.dishes.scan(None).await? {
+            state.insert_dish(&dish);
+        }
+        for ingredient in self.ingredients.scan().await? {
+            state.insert(
+                (DocKind::Ingredient, ingredient.name.clone()),
+                ingredient_terms(&ingredient),
+            );
+        }
+        *self.state.write().expect("poisoned lock") = Some((built, state));
+        Ok(())
+    }
+}
+
+impl IndexState {
+    fn insert_dish(&mut self, dish: &Dish) {
+        self.remove_dish(&dish.name);
+        self.insert((DocKind::Dish, dish.name.clone()), dish_terms(dish));
+        let mut recipe: Vec<String> = vec![];
+        for ingredient in &dish.ingredients {
+            if !recipe.contains(&ingredient.name) {
+                recipe.push(ingredient.name.clone());
+            }
+        }
+        for ingredient in &recipe {
+            self.used_in
+                .entry(ingredient.clone())
+                .or_default()
+                .insert(dish.name.clone());
+        }
+        self.recipes.insert(dish.name.clone(), recipe);
+    }
+    fn remove_dish(&mut self, name: &str) {
+        self.remove(&(DocKind::Dish, name.to_string()));
+        if let Some(old) = self.recipes.remove(name) {
+            for ingredient in old {
+                if let Some(dishes) = self.used_in.get_mut(&ingredient) {
+                    dishes.remove(name);
+                }
+            }
+        }
+    }
+    fn insert(&mut self, key: DocKey, terms: HashMap<String, f32>) {
+        self.remove(&key);
+        for term in terms.keys() {
+            self.postings
+                .entry(term.clone())
+                .or_default()
+                .insert(key.clone());
+        }
+        self.docs.insert(key, terms);
+    }
+    fn remove(&mut self, key: &DocKey) {
+        if let Some(old) = self.docs.remove(key) {
+            for term in old.keys() {
+                if let Some(keys) = self.postings.get_mut(term) {
+                    keys.remove(key);
+                }
+            }
+        }
+    }
+}
+
+fn dish_terms(dish: &Dish) -> HashMap<String, f32> {
+    let mut fields = vec![(dish.name.as_str(), NAME_WEIGHT)];
+    if let Some(style) = &dish.style {
+        fields.push((style, TAG_WEIGHT));
+    }
+    for ingredient in &dish.ingredients {
+        fields.push((&ingredient.name, INGREDIENT_WEIGHT));
+    }
+    field_terms(&fields)
+}
+
+fn ingredient_terms(ingredient: &Ingredient) -> HashMap<String, f32> {
+    field_terms(&[
+        (&ingredient.name, NAME_WEIGHT),
+        (&ingredient.category, TAG_WEIGHT),
+    ])
+}
+
+/// Terms of all fields, each with the weight of the best field holding it.
+fn field_terms(fields: &[(&str, f32)]) -> HashMap<String, f32> {
+    let mut weights: HashMap<String, f32> = HashMap::new();
+    for (text, field_weight) in fields {
+        for term in terms(text).into_keys() {
+            let weight = weights.entry(term).or_default();
+            *weight = weight.max(*field_weight);
+        }
+    }
+    weights
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{2E80}'..='\u{9FFF}'
+        | '\u{AC00}'..='\u{D7AF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{20000}'..='\u{2FA1F}')
+}
+
+/// Splits text into weighted terms. CJK text has no word boundaries, so it
+/// is indexed as single characters and character bigrams. Other words are
+/// indexed whole and as padded trigrams, which lets misspellings still
+/// share most of their terms with the original.
+fn terms(text: &str) -> HashMap<String, f32> {
+    let mut terms = HashMap::new();
+    let mut add = |term: String, weight: f32| {
+        let w = terms.entry(term).or_insert(0.0);
+        *w = f32::max(*w, weight);
+    };
+    let text = text.to_lowercase();
+    let mut cjk_run: Vec<char> = vec![];
+    let mut word: Vec<char> = vec![];
+    let mut flush = |cjk_run: &mut Vec<char>, word: &mut Vec<char>| {
+        for (i, c) in cjk_run.iter().enumerate() {
+            add(c.to_string(), 1.0);
+            if let Some(next) = cjk_run.get(i + 1) {
+                add(format!("{}{}", c, next), 2.0);
+            }
+        }
+        if !word.is_empty() {
+            add(word.iter().collect(), 2.0);
+            let padded: Vec<char> = std::iter::once('^')
+                .chain(word.iter().copied())
+                .chain(std::iter::once('$'))
+                .collect();
+            for trigram in padded.windows(3) {
+                add(trigram.iter().collect(), 1.0);
+            }
+        }
+        cjk_run.clear();
+        word.clear();
+    };
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !word.is_empty() {
+                flush(&mut vec![], &mut word);
+            }
+            cjk_run.push(c);
+        } else if c.is_alphanumeric() {
+            if !cjk_run.is_empty() {
+                flush(&mut cjk_run, &mut vec![]);
+            }
+            word.push(c);
+        } else {
+            flush(&mut cjk_run, &mut word);
+        }
+    }
+    flush(&mut cjk_run, &mut word);
+    terms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(terms: HashMap<String, f32>) -> Vec<(String, f32)> {
+        let mut terms: Vec<_> = terms.into_iter().collect();
+        terms.sort_by(|a, b| a.0.cmp(&b.0));
+        terms
+    }
+
+    fn term(text: &str, weight: f32) -> (String, f32) {
+        (text.to_string(), weight)
+    }
+
+    #[test]
+    fn cjk_text_gives_characters_and_bigrams() {
+        assert_eq!(
+            sorted(terms("番茄炒蛋")),
+            [
+                term("炒", 1.0),
+                term("炒蛋", 2.0),
+                term("番", 1.0),
+                term("番茄", 2.0),
+                term("茄", 1.0),
+                term("茄炒", 2.0),
+                term("蛋", 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn words_give_the_word_and_padded_trigrams() {
+        assert_eq!(
+            sorted(terms("Egg")),
+            [term("^eg", 1.0), term("egg", 2.0), term("gg$", 1.0)]
+        );
+    }
+
+    #[test]
+    fn mixed_text_is_split_at_script_changes_and_punctuation() {
+        let terms = terms("小炒pork, 肉");
+        assert!(terms.contains_key("小炒"));
+        assert!(terms.contains_key("pork"));
+        assert!(terms.contains_key("肉"));
+        assert!(!terms.contains_key("炒p"));
+        assert!(!terms.contains_key("炒肉"));
+    }
+
+    #[test]
+    fn field_terms_keep_the_best_weight() {
+        let weights = field_terms(&[("番茄", NAME_WEIGHT), ("番茄", INGREDIENT_WEIGHT)]);
+        assert_eq!(weights.get("番茄"), Some(&NAME_WEIGHT));
+        let dish = Dish {
+            name: "番茄炒蛋".to_string(),
+            style: Some("家常".to_string()),
+            ..Dish::default()
+        };
+        let weights = dish_terms(&dish);
+        assert_eq!(weights.get("家常"), Some(&TAG_WEIGHT));
+        assert_eq!(weights.get("炒蛋"), Some(&NAME_WEIGHT));
+    }
+}
@@ -0,0 +1,1322 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Error};
+use async_graphql::OutputType;
+use chrono::NaiveDate;
+use rusqlite::{params, params_from_iter, types::Value, Connection, OptionalExtension, Row};
+
+use super::store::{
+    check_rename, named_in, rename_in, BatchMode, CookLogStore, DishPatch, DishStore,
+    IngredientStore, MealPlanStore, PantryStore, ShareStore, UserRecord, UserStore, WriteCondition,
+};
+use crate::graphql::{
+    CookEntry, Dish, DishFilter, Ingredient, Meal, MealSlot, Page, PantryItem, Quantity,
+    RawRecipeIngredient, ShareableMenu,
+};
+
+/// Fills in data that SQL alone cannot compute after a schema change.
+type Backfill = fn(&Connection) -> Result<(), Error>;
+
+/// Schema migrations, applied in order, each with an optional backfill.
+/// `PRAGMA user_version` records how many of them a database has seen.
+const MIGRATIONS: &[(&str, Option<Backfill>)] = &[
+    (include_str!("sqlite/migrations/0001_init.sql"), None),
+    (
+        include_str!("sqlite/migrations/0002_parsed_quantities.sql"),
+        Some(parse_quantities),
+    ),
+    (
+        include_str!("sqlite/migrations/0003_conversion_hints.sql"),
+        None,
+    ),
+    (include_str!("sqlite/migrations/0004_meal_plans.sql"), None),
+    (include_str!("sqlite/migrations/0005_cook_log.sql"), None),
+    (include_str!("sqlite/migrations/0006_pantry.sql"), None),
+];
+
+const DISH_COLUMNS: &str =
+    "name, meal, spicy, cook_time, recipe_link, serving, one_dish, soup, style, photo, suppressed";
+
+const INGREDIENT_COLUMNS: &str = "name, category, density, piece_weight";
+
+const UPSERT_DISH: &str = "INSERT INTO dishes (
+    name, meal, spicy, cook_time, recipe_link, serving, one_dish, soup, style, photo, suppressed
+) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+ON CONFLICT (name) DO UPDATE SET
+    meal = excluded.meal,
+    spicy = excluded.spicy,
+    cook_time = excluded.cook_time,
+    recipe_link = excluded.recipe_link,
+    serving = excluded.serving,
+    one_dish = excluded.one_dish,
+    soup = excluded.soup,
+    style = excluded.style,
+    photo = COALESCE(excluded.photo, dishes.photo),
+    suppressed = excluded.suppressed
+";
+
+/// SQLite implementation of the dish, ingredient, share and user stores.
+/// Photos are not kept in the database, pair it with a `PhotoStore`.
+pub(crate) struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    pub(crate) fn open(path: impl AsRef<Path>) -> Result<SqliteStore, Error> {
+        SqliteStore::from_connection(Connection::open(path)?)
+    }
+
+    /// Migrates the database of `conn` to the latest schema.
+    fn from_connection(mut conn: Connection) -> Result<SqliteStore, Error> {
+        conn.pragma_update(None, "foreign_keys", true)?;
+        migrate(&mut conn)?;
+        Ok(SqliteStore {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs `f` against the connection on the blocking thread pool.
+    async fn with_conn<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, Error> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&mut conn.lock().expect("poisoned lock"))).await?
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), Error> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "database schema version {} is newer than this binary",
+            version
+        ));
+    }
+    for (i, (migration, backfill)) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        if let Some(backfill) = backfill {
+            backfill(&tx)?;
+        }
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Fills the parsed quantity columns from the quantity text.
+fn parse_quantities(conn: &Connection) -> Result<(), Error> {
+    let mut select = conn.prepare("SELECT dish, position, quantity FROM dish_ingredients")?;
+    let mut update = conn.prepare(
+        "UPDATE dish_ingredients SET amount = ?3, unit = ?4, note = ?5
+        WHERE dish = ?1 AND position = ?2",
+    )?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let dish: String = row.get("dish")?;
+        let position: i64 = row.get("position")?;
+        let quantity = Quantity::parse(&row.get::<_, String>("quantity")?);
+        update.execute(params![
+            dish,
+            position,
+            quantity.amount,
+            quantity.unit,
+            quantity.note
+        ])?;
+    }
+    Ok(())
+}
+
+/// A `WHERE` clause built from conditions that all have to hold.
+#[derive(Default)]
+struct Where {
+    conditions: Vec<String>,
+    args: Vec<Value>,
+}
+
+impl Where {
+    fn and(&mut self, condition: impl Into<String>, args: impl IntoIterator<Item = Value>) {
+        self.conditions.push(condition.into());
+        self.args.extend(args);
+    }
+    fn into_sql(self) -> (String, Vec<Value>) {
+        match self.conditions.is_empty() {
+            true => (String::new(), self.args),
+            false => (
+                format!("WHERE {}", self.conditions.join(" AND ")),
+                self.args,
+            ),
+        }
+    }
+}
+
+/// Selects the rows after `after` in key order, one more than `limit` to
+/// tell whether another page follows.
+fn page_tail(
+    mut condition: Where,
+    after: Option<String>,
+    limit: Option<usize>,
+) -> (String, Vec<Value>) {
+    if let Some(after) = after {
+        condition.and("name > ?", [Value::Text(after)]);
+    }
+    let (clause, args) = condition.into_sql();
+    let limit = match limit {
+        Some(limit) => format!("LIMIT {}", limit + 1),
+        None => String::new(),
+    };
+    (format!("{} ORDER BY name {}", clause, limit), args)
+}
+
+/// Translates a `DishFilter` into a condition on `dishes` with the same
+/// semantics as `DishFilter::matches`.
+fn dish_condition(filter: &DishFilter, condition: &mut Where) {
+    if let Some(meal) = filter.meal {
+        condition.and("meal = ?", [Value::from(meal_to_sql(meal).to_string())]);
+    }
+    for (column, flag) in [
+        ("soup", filter.soup),
+        ("one_dish", filter.one_dish),
+        ("suppressed", filter.suppressed),
+    ] {
+        if let Some(flag) = flag {
+            condition.and(format!("COALESCE({}, 0) = ?", column), [Value::from(flag)]);
+        }
+    }
+    if let Some(style) = &filter.style {
+        condition.and("style = ?", [Value::from(style.clone())]);
+    }
+    if let Some(max_cook_time) = filter.max_cook_time {
+        condition.and("cook_time <= ?", [Value::from(max_cook_time)]);
+    }
+    if let Some(min_spicy) = filter.min_spicy {
+        condition.and("spicy >= ?", [Value::from(min_spicy as f64)]);
+    }
+    if let Some(max_spicy) = filter.max_spicy {
+        condition.and("spicy <= ?", [Value::from(max_spicy as f64)]);
+    }
+    if let Some(ingredient) = &filter.ingredient {
+        condition.and(
+            "EXISTS (SELECT 1 FROM dish_ingredients
+                WHERE dish_ingredients.dish = dishes.name AND ingredient = ?)",
+            [Value::from(ingredient.clone())],
+        );
+    }
+    for filter in filter.and.iter().flatten() {
+        dish_condition(filter, condition);
+    }
+    if let Some(filters) = &filter.or {
+        let mut alternatives = vec![];
+        for filter in filters {
+            let mut alternative = Where::default();
+            dish_condition(filter, &mut alternative);
+            match alternative.conditions.is_empty() {
+                true => alternatives.push("1".to_string()),
+                false => alternatives.push(format!("({})", alternative.conditions.join(" AND "))),
+            }
+            condition.args.append(&mut alternative.args);
+        }
+        match alternatives.is_empty() {
+            true => condition.and("0", []),
+            false => condition.and(format!("({})", alternatives.join(" OR ")), []),
+        }
+    }
+}
+
+fn dish_where(filter: Option<&DishFilter>) -> Where {
+    let mut condition = Where::default();
+    if let Some(filter) = filter {
+        dish_condition(filter, &mut condition);
+    }
+    condition
+}
+
+fn into_page<T: OutputType>(
+    mut items: Vec<T>,
+    limit: Option<usize>,
+    key: impl Fn(&T) -> &String,
+) -> Page<T> {
+    match limit {
+        Some(limit) if items.len() > limit => {
+            items.truncate(limit);
+            let next_cursor = items.last().map(|item| key(item).clone());
+            Page { items, next_cursor }
+        }
+        _ => Page {
+            items,
+            next_cursor: None,
+        },
+    }
+}
+
+fn count(conn: &Connection, table: &str, condition: Where) -> Result<usize, Error> {
+    let (clause, args) = condition.into_sql();
+    Ok(conn.query_row(
+        &format!("SELECT COUNT(*) FROM {} {}", table, clause),
+        params_from_iter(args),
+        |row| row.get(0),
+    )?)
+}
+
+fn placeholders(n: usize) -> String {
+    vec!["?"; n].join(", ")
+}
+
+/// Whether `table` has a row with primary key `name`.
+fn exists(conn: &Connection, table: &str, name: &str) -> Result<bool, Error> {
+    Ok(conn
+        .query_row(
+            &format!("SELECT 1 FROM {} WHERE name = ?1", table),
+            params![name],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some())
+}
+
+/// Deletes the rows of `table` whose `column` is one of `keys`.
+fn delete_keys(conn: &Connection, table: &str, column: &str, keys: &[String]) -> Result<(), Error> {
+    conn.execute(
+        &format!(
+            "DELETE FROM {} WHERE {} IN ({})",
+            table,
+            column,
+            placeholders(keys.len())
+        ),
+        params_from_iter(keys),
+    )?;
+    Ok(())
+}
+
+fn meal_to_sql(meal: Meal) -> &'static str {
+    match meal {
+        Meal::Lunch => "Lunch",
+        Meal::Dinner => "Dinner",
+    }
+}
+
+fn meal_from_sql(meal: Option<String>) -> Result<Option<Meal>, Error> {
+    meal.map(|meal| match meal.as_str() {
+        "Lunch" => Ok(Meal::Lunch),
+        "Dinner" => Ok(Meal::Dinner),
+        other => Err(anyhow!("unknown meal {}", other)),
+    })
+    .transpose()
+}
+
+fn dish_from_row(row: &Row) -> Result<Dish, Error> {
+    let photo: Option<String> = row.get("photo")?;
+    Ok(Dish {
+        name: row.get("name")?,
+        meal: meal_from_sql(row.get("meal")?)?,
+        ingredients: vec![],
+        spicy: row.get("spicy")?,
+        cook_time: row.get("cook_time")?,
+        recipe_link: row.get("recipe_link")?,
+        serving: row.get("serving")?,
+        one_dish: row.get("one_dish")?,
+        soup: row.get("soup")?,
+        style: row.get("style")?,
+        photo: photo.map(|p| serde_json::from_str(&p)).transpose()?,
+        suppressed: row.get("suppressed")?,
+    })
+}
+
+/// Loads dishes selected by `tail`, the part of a `SELECT` after `FROM`,
+/// together with their recipe ingredients.
+fn select_dishes(conn: &Connection, tail: &str, args: &[Value]) -> Result<Vec<Dish>, Error> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM dishes {}", DISH_COLUMNS, tail))?;
+    let mut rows = stmt.query(params_from_iter(args))?;
+    let mut dishes = vec![];
+    while let Some(row) = rows.next()? {
+        dishes.push(dish_from_row(row)?);
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT dish, ingredient, quantity, amount, unit, note FROM dish_ingredients
+        WHERE dish IN (SELECT name FROM dishes {})
+        ORDER BY dish, position",
+        tail
+    ))?;
+    let mut rows = stmt.query(params_from_iter(args))?;
+    let mut ingredients: HashMap<String, Vec<RawRecipeIngredient>> = HashMap::new();
+    while let Some(row) = rows.next()? {
+        ingredients
+            .entry(row.get("dish")?)
+            .or_default()
+            .push(RawRecipeIngredient {
+                name: row.get("ingredient")?,
+                quantity: Quantity {
+                    amount: row.get("amount")?,
+                    unit: row.get("unit")?,
+                    note: row.get("note")?,
+                    text: row.get("quantity")?,
+                },
+            });
+    }
+    for dish in dishes.iter_mut() {
+        dish.ingredients = ingredients.remove(&dish.name).unwrap_or_default();
+    }
+    Ok(dishes)
+}
+
+/// A dish without `photo` keeps its stored photo.
+fn upsert_dish(conn: &Connection, item: &Dish) -> Result<(), Error> {
+    conn.execute(
+        UPSERT_DISH,
+        params![
+            item.name,
+            item.meal.map(meal_to_sql),
+            item.spicy,
+            item.cook_time,
+            item.recipe_link,
+            item.serving,
+            item.one_dish,
+            item.soup,
+            item.style,
+            item.photo.as_ref().map(serde_json::to_string).transpose()?,
+            item.suppressed,
+        ],
+    )?;
+    replace_recipe(conn, &item.name, &item.ingredients)
+}
+
+/// Updates only the columns the patch changes.
+fn patch_dish(conn: &Connection, patch: &DishPatch) -> Result<(), Error> {
+    fn change<T: Clone + Into<Value>>(
+        columns: &mut Vec<(&'static str, Value)>,
+        column: &'static str,
+        value: &Option<Option<T>>,
+    ) {
+        if let Some(value) = value {
+            columns.push((column, value.clone().into()));
+        }
+    }
+    let mut columns = vec![];
+    let meal = patch
+        .meal
+        .map(|meal| meal.map(|meal| meal_to_sql(meal).to_string()));
+    change(&mut columns, "meal", &meal);
+    change(&mut columns, "spicy", &patch.spicy);
+    change(&mut columns, "cook_time", &patch.cook_time);
+    change(&mut columns, "recipe_link", &patch.recipe_link);
+    change(&mut columns, "serving", &patch.serving);
+    change(&mut columns, "one_dish", &patch.one_dish);
+    change(&mut columns, "soup", &patch.soup);
+    change(&mut columns, "style", &patch.style);
+    let photo = patch
+        .photo
+        .as_ref()
+        .map(|photo| photo.as_ref().map(serde_json::to_string).transpose())
+        .transpose()?;
+    change(&mut columns, "photo", &photo);
+    change(&mut columns, "suppressed", &patch.suppressed);
+    if !columns.is_empty() {
+        let assignments: Vec<String> = columns
+            .iter()
+            .enumerate()
+            .map(|(i, (column, _))| format!("{} = ?{}", column, i + 2))
+            .collect();
+        let args = std::iter::once(Value::from(patch.name.clone()))
+            .chain(columns.into_iter().map(|(_, value)| value));
+        conn.execute(
+            &format!(
+                "UPDATE dishes SET {} WHERE name = ?1",
+                assignments.join(", ")
+            ),
+            params_from_iter(args),
+        )?;
+    }
+    if let Some(ingredients) = &patch.ingredients {
+        replace_recipe(conn, &patch.name, ingredients)?;
+    }
+    Ok(())
+}
+
+fn replace_recipe(
+    conn: &Connection,
+    dish: &str,
+    ingredients: &[RawRecipeIngredient],
+) -> Result<(), Error> {
+    conn.execute(
+        "DELETE FROM dish_ingredients WHERE dish = ?1",
+        params![dish],
+    )?;
+    for (position, ingredient) in ingredients.iter().enumerate() {
+        conn.execute(
+            "INSERT INTO dish_ingredients (
+                dish, position, ingredient, quantity, amount, unit, note
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                dish,
+                position,
+                ingredient.name,
+                ingredient.quantity.text,
+                ingredient.quantity.amount,
+                ingredient.quantity.unit,
+                ingredient.quantity.note
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl DishStore for SqliteStore {
+    async fn get_many(&self, names: &[String]) -> Result<Vec<Dish>, Error> {
+        let names = names.to_vec();
+        self.with_conn(move |conn| {
+            select_dishes(
+                conn,
+                &format!("WHERE name IN ({})", placeholders(names.len())),
+                &names.into_iter().map(Value::from).collect::<Vec<_>>(),
+            )
+        })
+        .await
+    }
+    async fn scan_page(
+        &self,
+        filter: Option<&DishFilter>,
+        after: Option<String>,
+        limit: Option<usize>,
+    ) -> Result<Page<Dish>, Error> {
+        let (tail, args) = page_tail(dish_where(filter), after, limit);
+        self.with_conn(move |conn| {
+            let dishes = select_dishes(conn, &tail, &args)?;
+            Ok(into_page(dishes, limit, |d| &d.name))
+        })
+        .await
+    }
+    async fn scan(&self, filter: Option<&DishFilter>) -> Result<Vec<Dish>, Error> {
+        let (tail, args) = page_tail(dish_where(filter), None, None);
+        self.with_conn(move |conn| select_dishes(conn, &tail, &args))
+            .await
+    }
+    async fn count(&self, filter: Option<&DishFilter>) -> Result<usize, Error> {
+        let condition = dish_where(filter);
+        self.with_conn(move |conn| count(conn, "dishes", condition))
+            .await
+    }
+    async fn migrate_quantities(&self) -> Result<usize, Error> {
+        // Schema migrations parse quantities on startup
+        Ok(0)
+    }
+    async fn update_many_if(
+        &self,
+        items: &[Dish],
+        condition: WriteCondition,
+        mode: BatchMode,
+        new_ingredients: &[Ingredient],
+    ) -> Result<Vec<bool>, Error> {
+        let items = items.to_vec();
+        let new_ingredients = new_ingredients.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let mut held = vec![];
+            for item in &items {
+                held.push(condition.holds(exists(&tx, "dishes", &item.name)?));
+            }
+            if mode.writes(&held) {
+                let written: Vec<&Dish> = items
+                    .iter()
+                    .zip(&held)
+                    .filter(|(_, held)| **held)
+                    .map(|(item, _)| item)
+                    .collect();
+                let recipes = written.iter().map(|item| item.ingredients.as_slice());
+                insert_absent_ingredients(&tx, &named_in(&new_ingredients, recipes))?;
+                for item in written {
+                    upsert_dish(&tx, item)?;
+                }
+                tx.commit()?;
+            }
+            Ok(held)
+        })
+        .await
+    }
+    async fn patch_many(
+        &self,
+        patches: &[DishPatch],
+        mode: BatchMode,
+        new_ingredients: &[Ingredient],
+    ) -> Result<Vec<bool>, Error> {
+        let patches = patches.to_vec();
+        let new_ingredients = new_ingredients.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let mut found = vec![];
+            for patch in &patches {
+                found.push(exists(&tx, "dishes", &patch.name)?);
+            }
+            if mode.writes(&found) {
+                let written: Vec<&DishPatch> = patches
+                    .iter()
+                    .zip(&found)
+                    .filter(|(_, found)| **found)
+                    .map(|(patch, _)| patch)
+                    .collect();
+                let recipes = written
+                    .iter()
+                    .filter_map(|patch| patch.ingredients.as_deref());
+                insert_absent_ingredients(&tx, &named_in(&new_ingredients, recipes))?;
+                for patch in written {
+                    patch_dish(&tx, patch)?;
+                }
+                tx.commit()?;
+            }
+            Ok(found)
+        })
+        .await
+    }
+    async fn rename(&self, from: &str, to: &str) -> Result<(), Error> {
+        let (from, to) = (from.to_owned(), to.to_owned());
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            check_rename(
+                "dish",
+                &from,
+                &to,
+                exists(&tx, "dishes", &from)?,
+                exists(&tx, "dishes", &to)?,
+            )?;
+            // Recipe lines follow through their `ON UPDATE CASCADE` reference
+            tx.execute(
+                "UPDATE dishes SET name = ?2 WHERE name = ?1",
+                params![from, to],
+            )?;
+            // Entries left by a deleted dish of the same name are merged
+            tx.execute(
+                "UPDATE OR REPLACE cook_log SET dish_name = ?2 WHERE dish_name = ?1",
+                params![from, to],
+            )?;
+            let mut stmt = tx.prepare(
+                "SELECT date, meal, dishes FROM meal_plans
+                WHERE EXISTS (SELECT 1 FROM json_each(meal_plans.dishes) WHERE value = ?1)",
+            )?;
+            let slots = stmt
+                .query_map(params![from], |row| {
+                    Ok((
+                        row.get::<_, String>("date")?,
+                        row.get::<_, String>("meal")?,
+                        row.get::<_, String>("dishes")?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            drop(stmt);
+            for (date, meal, dishes) in slots {
+                let mut dishes: Vec<String> = serde_json::from_str(&dishes)?;
+                rename_in(&mut dishes, &from, &to);
+                tx.execute(
+                    "UPDATE meal_plans SET dishes = ?3 WHERE date = ?1 AND meal = ?2",
+                    params![date, meal, serde_json::to_string(&dishes)?],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+    async fn delete_many(&self, names: &[String]) -> Result<(), Error> {
+        let names = names.to_vec();
+        self.with_conn(move |conn| delete_keys(conn, "dishes", "name", &names))
+            .await
+    }
+}
+
+fn ingredient_from_row(row: &Row) -> rusqlite::Result<Ingredient> {
+    Ok(Ingredient {
+        name: row.get("name")?,
+        category: row.get("category")?,
+        density: row.get("density")?,
+        piece_weight: row.get("piece_weight")?,
+    })
+}
+
+fn upsert_ingredient(conn: &Connection, item: &Ingredient) -> Result<(), Error> {
+    conn.execute(
+        "INSERT INTO ingredients (name, category, density, piece_weight)
+        VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT (name) DO UPDATE SET
+            category = excluded.category,
+            density = excluded.density,
+            piece_weight = excluded.piece_weight",
+        params![item.name, item.category, item.density, item.piece_weight],
+    )?;
+    Ok(())
+}
+
+/// Recipe lines reference `ingredients`, so these go in before the dishes.
+fn insert_absent_ingredients(conn: &Connection, items: &[Ingredient]) -> Result<(), Error> {
+    for item in items {
+        conn.execute(
+            "INSERT INTO ingredients (name, category, density, piece_weight)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (name) DO NOTHING",
+            params![item.name, item.category, item.density, item.piece_weight],
+        )?;
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl IngredientStore for SqliteStore {
+    async fn get_many(&self, names: &[String]) -> Result<Vec<Ingredient>, Error> {
+        let names = names.to_vec();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM ingredients WHERE name IN ({})",
+                INGREDIENT_COLUMNS,
+                placeholders(names.len())
+            ))?;
+            let ingredients = stmt
+                .query_map(params_from_iter(&names), ingredient_from_row)?
+                .collect::<Result<_, _>>()?;
+            Ok(ingredients)
+        })
+        .await
+    }
+    async fn scan_page(
+        &self,
+        after: Option<String>,
+        limit: Option<usize>,
+    ) -> Result<Page<Ingredient>, Error> {
+        self.with_conn(move |conn| {
+            let (tail, args) = page_tail(Where::default(), after, limit);
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM ingredients {}",
+                INGREDIENT_COLUMNS, tail
+            ))?;
+            let ingredients = stmt
+                .query_map(params_from_iter(&args), ingredient_from_row)?
+                .collect::<Result<_, _>>()?;
+            Ok(into_page(ingredients, limit, |i| &i.name))
+        })
+        .await
+    }
+    async fn count(&self) -> Result<usize, Error> {
+        self.with_conn(|conn| count(conn, "ingredients", Where::default()))
+            .await
+    }
+    async fn update_many_if(
+        &self,
+        items: &[Ingredient],
+        condition: WriteCondition,
+        mode: BatchMode,
+    ) -> Result<Vec<bool>, Error> {
+        let items = items.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let mut held = vec![];
+            for item in &items {
+                held.push(condition.holds(exists(&tx, "ingredients", &item.name)?));
+            }
+            if mode.writes(&held) {
+                for (item, _) in items.iter().zip(&held).filter(|(_, held)| **held) {
+                    upsert_ingredient(&tx, item)?;
+                }
+                tx.commit()?;
+            }
+            Ok(held)
+        })
+        .await
+    }
+    async fn rename(&self, from: &str, to: &str) -> Result<Vec<Dish>, Error> {
+        let (from, to) = (from.to_owned(), to.to_owned());
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            check_rename(
+                "ingredient",
+                &from,
+                &to,
+                exists(&tx, "ingredients", &from)?,
+                exists(&tx, "ingredients", &to)?,
+            )?;
+            // Recipe lines follow through their `ON UPDATE CASCADE` reference
+            tx.execute(
+                "UPDATE ingredients SET name = ?2 WHERE name = ?1",
+                params![from, to],
+            )?;
+            // Stock left by a deleted ingredient of the same name is replaced
+            tx.execute(
+                "UPDATE OR REPLACE pantry SET ingredient_name = ?2 WHERE ingredient_name = ?1",
+                params![from, to],
+            )?;
+            let rewritten = select_dishes(
+                &tx,
+                "WHERE name IN (SELECT dish FROM dish_ingredients WHERE ingredient = ?)",
+                &[to.into()],
+            )?;
+            tx.commit()?;
+            Ok(rewritten)
+        })
+        .await
+    }
+    async fn delete_many(&self, names: &[String]) -> Result<Vec<Dish>, Error> {
+        let names = names.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let rewritten: Vec<String> = tx
+                .prepare(&format!(
+                    "SELECT DISTINCT dish FROM dish_ingredients WHERE ingredient IN ({})",
+                    placeholders(names.len())
+                ))?
+                .query_map(params_from_iter(&names), |row| row.get("dish"))?
+                .collect::<Result<_, _>>()?;
+            delete_keys(&tx, "dish_ingredients", "ingredient", &names)?;
+            delete_keys(&tx, "ingredients", "name", &names)?;
+            let rewritten = select_dishes(
+                &tx,
+                &format!("WHERE name IN ({})", placeholders(rewritten.len())),
+                &rewritten.into_iter().map(Into::into).collect::<Vec<_>>(),
+            )?;
+            tx.commit()?;
+            Ok(rewritten)
+        })
+        .await
+    }
+}
+
+#[async_trait::async_trait]
+impl ShareStore for SqliteStore {
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<ShareableMenu>, Error> {
+        let keys = keys.to_vec();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT key, payload FROM shares WHERE key IN ({})",
+                placeholders(keys.len())
+            ))?;
+            let menus = stmt
+                .query_map(params_from_iter(&keys), |row| {
+                    Ok(ShareableMenu {
+                        key: row.get("key")?,
+                        payload: row.get("payload")?,
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+            Ok(menus)
+        })
+        .await
+    }
+    async fn put(&self, item: &ShareableMenu) -> Result<(), Error> {
+        let item = item.clone();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO shares (key, payload) VALUES (?1, ?2)",
+                params![item.key, item.payload],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        let keys = vec![key.to_owned()];
+        self.with_conn(move |conn| delete_keys(conn, "shares", "key", &keys))
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl MealPlanStore for SqliteStore {
+    async fn get_range(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<MealSlot>, Error> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT date, meal, dishes FROM meal_plans WHERE date BETWEEN ?1 AND ?2",
+            )?;
+            let mut rows = stmt.query(params![from.to_string(), to.to_string()])?;
+            let mut slots = vec![];
+            while let Some(row) = rows.next()? {
+                slots.push(MealSlot {
+                    date: row.get::<_, String>("date")?.parse()?,
+                    meal: meal_from_sql(row.get("meal")?)?
+                        .ok_or_else(|| anyhow!("meal slot without meal"))?,
+                    dish_names: serde_json::from_str(&row.get::<_, String>("dishes")?)?,
+                });
+            }
+            // Meals do not sort by their text
+            slots.sort_by_key(|slot| (slot.date, slot.meal));
+            Ok(slots)
+        })
+        .await
+    }
+    async fn put_many(&self, slots: &[MealSlot]) -> Result<(), Error> {
+        let slots = slots.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            for slot in slots {
+                let date = slot.date.to_string();
+                let meal = meal_to_sql(slot.meal);
+                if slot.dish_names.is_empty() {
+                    tx.execute(
+                        "DELETE FROM meal_plans WHERE date = ?1 AND meal = ?2",
+                        params![date, meal],
+                    )?;
+                } else {
+                    tx.execute(
+                        "INSERT OR REPLACE INTO meal_plans (date, meal, dishes) VALUES (?1, ?2, ?3)",
+                        params![date, meal, serde_json::to_string(&slot.dish_names)?],
+                    )?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+fn cook_entries(conn: &Connection, tail: &str, args: &[Value]) -> Result<Vec<CookEntry>, Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT dish_name, date, meal FROM cook_log {}",
+        tail
+    ))?;
+    let mut rows = stmt.query(params_from_iter(args))?;
+    let mut entries = vec![];
+    while let Some(row) = rows.next()? {
+        entries.push(CookEntry {
+            dish_name: row.get("dish_name")?,
+            date: row.get::<_, String>("date")?.parse()?,
+            meal: meal_from_sql(row.get("meal")?)?
+                .ok_or_else(|| anyhow!("cook log entry without meal"))?,
+        });
+    }
+    Ok(entries)
+}
+
+#[async_trait::async_trait]
+impl CookLogStore for SqliteStore {
+    async fn get_for_dishes(&self, dish_names: &[String]) -> Result<Vec<CookEntry>, Error> {
+        let args: Vec<Value> = dish_names.iter().cloned().map(Value::from).collect();
+        self.with_conn(move |conn| {
+            cook_entries(
+                conn,
+                &format!("WHERE dish_name IN ({})", placeholders(args.len())),
+                &args,
+            )
+        })
+        .await
+    }
+    async fn get_range(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<CookEntry>, Error> {
+        self.with_conn(move |conn| {
+            cook_entries(
+                conn,
+                "WHERE date BETWEEN ?1 AND ?2",
+                &[from.to_string().into(), to.to_string().into()],
+            )
+        })
+        .await
+    }
+    async fn put_many(&self, entries: &[CookEntry]) -> Result<Vec<CookEntry>, Error> {
+        let entries = entries.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let mut added = vec![];
+            for entry in entries {
+                let inserted = tx.execute(
+                    "INSERT OR IGNORE INTO cook_log (dish_name, date, meal) VALUES (?1, ?2, ?3)",
+                    params![
+                        entry.dish_name,
+                        entry.date.to_string(),
+                        meal_to_sql(entry.meal)
+                    ],
+                )?;
+                if inserted > 0 {
+                    added.push(entry);
+                }
+            }
+            tx.commit()?;
+            Ok(added)
+        })
+        .await
+    }
+}
+
+fn pantry_items(conn: &Connection, tail: &str, args: &[Value]) -> Result<Vec<PantryItem>, Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT ingredient_name, quantity, unit, purchased_on, expires_on FROM pantry {}",
+        tail
+    ))?;
+    let mut rows = stmt.query(params_from_iter(args))?;
+    let mut items = vec![];
+    while let Some(row) = rows.next()? {
+        let date = |column| -> Result<Option<NaiveDate>, Error> {
+            Ok(row
+                .get::<_, Option<String>>(column)?
+                .map(|date| date.parse())
+                .transpose()?)
+        };
+        items.push(PantryItem {
+            ingredient_name: row.get("ingredient_name")?,
+            quantity: row.get("quantity")?,
+            unit: row.get("unit")?,
+            purchased_on: date("purchased_on")?,
+            expires_on: date("expires_on")?,
+        });
+    }
+    Ok(items)
+}
+
+#[async_trait::async_trait]
+impl PantryStore for SqliteStore {
+    async fn get_many(&self, ingredient_names: &[String]) -> Result<Vec<PantryItem>, Error> {
+        let args: Vec<Value> = ingredient_names.iter().cloned().map(Value::from).collect();
+        self.with_conn(move |conn| {
+            pantry_items(
+                conn,
+                &format!("WHERE ingredient_name IN ({})", placeholders(args.len())),
+                &args,
+            )
+        })
+        .await
+    }
+    async fn scan(&self) -> Result<Vec<PantryItem>, Error> {
+        self.with_conn(|conn| pantry_items(conn, "", &[])).await
+    }
+    async fn put_many(&self, items: &[PantryItem]) -> Result<(), Error> {
+        let items = items.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            for item in items {
+                tx.execute(
+                    "INSERT OR REPLACE INTO pantry (
+                        ingredient_name, quantity, unit, purchased_on, expires_on
+                    ) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        item.ingredient_name,
+                        item.quantity,
+                        item.unit,
+                        item.purchased_on.map(|date| date.to_string()),
+                        item.expires_on.map(|date| date.to_string())
+                    ],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+    async fn delete_many(&self, ingredient_names: &[String]) -> Result<(), Error> {
+        let names = ingredient_names.to_vec();
+        self.with_conn(move |conn| delete_keys(conn, "pantry", "ingredient_name", &names))
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl UserStore for SqliteStore {
+    async fn get(&self, user_id: &str) -> Result<Option<UserRecord>, Error> {
+        let user_id = user_id.to_owned();
+        self.with_conn(move |conn| {
+            let user = conn
+                .query_row(
+                    "SELECT user_id, roles, hashed_password FROM users WHERE user_id = ?1",
+                    params![user_id],
+                    |row| {
+                        Ok((
+                            row.get::<_, String>("user_id")?,
+                            row.get::<_, String>("roles")?,
+                            row.get::<_, String>("hashed_password")?,
+                        ))
+                    },
+                )
+                .optional()?;
+            user.map(|(user_id, roles, hashed_password)| {
+                Ok(UserRecord {
+                    user_id,
+                    roles: serde_json::from_str(&roles)?,
+                    hashed_password,
+                })
+            })
+            .transpose()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An in-memory database that has seen the first `version` migrations.
+    fn database_at(version: usize) -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+        for (migration, backfill) in &MIGRATIONS[..version] {
+            let tx = conn.transaction().unwrap();
+            tx.execute_batch(migration).unwrap();
+            if let Some(backfill) = backfill {
+                backfill(&tx).unwrap();
+            }
+            tx.commit().unwrap();
+        }
+        conn.pragma_update(None, "user_version", version).unwrap();
+        conn
+    }
+
+    fn store() -> SqliteStore {
+        SqliteStore::from_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn line(name: &str, quantity: &str) -> RawRecipeIngredient {
+        RawRecipeIngredient {
+            name: name.to_string(),
+            quantity: Quantity::parse(quantity),
+        }
+    }
+
+    fn ingredient(name: &str) -> Ingredient {
+        Ingredient {
+            name: name.to_string(),
+            category: "未分类".to_string(),
+            density: None,
+            piece_weight: None,
+        }
+    }
+
+    fn dish(name: &str, ingredients: Vec<RawRecipeIngredient>) -> Dish {
+        Dish {
+            name: name.to_string(),
+            meal: Some(Meal::Dinner),
+            ingredients,
+            spicy: Some(1.0),
+            ..Default::default()
+        }
+    }
+
+    fn date(text: &str) -> NaiveDate {
+        text.parse().unwrap()
+    }
+
+    fn names(dishes: &[&str]) -> Vec<String> {
+        dishes.iter().map(|dish| dish.to_string()).collect()
+    }
+
+    fn recipe(dish: &Dish) -> Vec<(&str, &str)> {
+        dish.ingredients
+            .iter()
+            .map(|line| (line.name.as_str(), line.quantity.text.as_str()))
+            .collect()
+    }
+
+    /// Adds `dishes` with their ingredients to the store.
+    async fn seed(store: &SqliteStore, dishes: &[Dish]) {
+        let new_ingredients: Vec<Ingredient> = dishes
+            .iter()
+            .flat_map(|dish| dish.ingredients.iter())
+            .map(|line| ingredient(&line.name))
+            .collect();
+        let held = DishStore::update_many_if(
+            store,
+            dishes,
+            WriteCondition::Absent,
+            BatchMode::Atomic,
+            &new_ingredients,
+        )
+        .await
+        .unwrap();
+        assert!(held.iter().all(|held| *held));
+    }
+
+    async fn get_dish(store: &SqliteStore, name: &str) -> Option<Dish> {
+        DishStore::get_many(store, &names(&[name]))
+            .await
+            .unwrap()
+            .pop()
+    }
+
+    #[tokio::test]
+    async fn migrating_parses_the_quantities_of_older_databases() {
+        let conn = database_at(1);
+        conn.execute_batch(
+            "INSERT INTO ingredients (name, category) VALUES ('猪肉', '肉类');
+            INSERT INTO dishes (name, meal) VALUES ('红烧肉', 'Dinner');
+            INSERT INTO dish_ingredients (dish, position, ingredient, quantity)
+            VALUES ('红烧肉', 0, '猪肉', '500g 五花');",
+        )
+        .unwrap();
+        let store = SqliteStore::from_connection(conn).unwrap();
+        let version: usize = store
+            .with_conn(|conn| Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?))
+            .await
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+        let dish = get_dish(&store, "红烧肉").await.unwrap();
+        let quantity = &dish.ingredients[0].quantity;
+        assert_eq!(quantity.amount, Some(500.0));
+        assert_eq!(quantity.unit.as_deref(), Some("g"));
+        assert_eq!(quantity.note.as_deref(), Some("五花"));
+        assert_eq!(quantity.text, "500g 五花");
+    }
+
+    #[test]
+    fn newer_databases_are_refused() {
+        let conn = database_at(MIGRATIONS.len());
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        let error = SqliteStore::from_connection(conn).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "database schema version {} is newer than this binary",
+                MIGRATIONS.len() + 1
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn dishes_round_trip_through_upserts_and_patches() {
+        let store = store();
+        seed(
+            &store,
+            &[dish(
+                "红烧肉",
+                vec![line("猪肉", "500g"), line("冰糖", "适量")],
+            )],
+        )
+        .await;
+        let held = DishStore::update_many_if(
+            &store,
+            &[dish("红烧肉", vec![])],
+            WriteCondition::Absent,
+            BatchMode::Atomic,
+            &[],
+        )
+        .await
+        .unwrap();
+        assert_eq!(held, [false]);
+
+        let patch = DishPatch {
+            name: "红烧肉".to_string(),
+            spicy: Some(None),
+            style: Some(Some("本帮".to_string())),
+            ingredients: Some(vec![line("五花肉", "1斤")]),
+            ..Default::default()
+        };
+        let found = store
+            .patch_many(&[patch], BatchMode::Atomic, &[ingredient("五花肉")])
+            .await
+            .unwrap();
+        assert_eq!(found, [true]);
+        let dish = get_dish(&store, "红烧肉").await.unwrap();
+        assert!(dish.meal == Some(Meal::Dinner));
+        assert_eq!(dish.spicy, None);
+        assert_eq!(dish.style.as_deref(), Some("本帮"));
+        assert_eq!(recipe(&dish), [("五花肉", "1斤")]);
+
+        let missing = DishPatch {
+            name: "糖醋排骨".to_string(),
+            ..Default::default()
+        };
+        let found = store
+            .patch_many(&[missing], BatchMode::Atomic, &[])
+            .await
+            .unwrap();
+        assert_eq!(found, [false]);
+    }
+
+    #[tokio::test]
+    async fn renaming_a_dish_moves_its_recipe_log_and_plans() {
+        let store = store();
+        seed(&store, &[dish("红烧肉", vec![line("猪肉", "500g")])]).await;
+        let entry = CookEntry {
+            dish_name: "红烧肉".to_string(),
+            date: date("2024-03-01"),
+            meal: Meal::Dinner,
+        };
+        CookLogStore::put_many(&store, &[entry]).await.unwrap();
+        let slot = MealSlot {
+            date: date("2024-03-02"),
+            meal: Meal::Lunch,
+            dish_names: names(&["炒青菜", "红烧肉"]),
+        };
+        MealPlanStore::put_many(&store, &[slot]).await.unwrap();
+
+        DishStore::rename(&store, "红烧肉", "东坡肉").await.unwrap();
+        assert!(get_dish(&store, "红烧肉").await.is_none());
+        let dish = get_dish(&store, "东坡肉").await.unwrap();
+        assert_eq!(recipe(&dish), [("猪肉", "500g")]);
+        let entries = store
+            .get_for_dishes(&names(&["红烧肉", "东坡肉"]))
+            .await
+            .unwrap();
+        let logged: Vec<&str> = entries.iter().map(|e| e.dish_name.as_str()).collect();
+        assert_eq!(logged, ["东坡肉"]);
+        let slots = MealPlanStore::get_range(&store, date("2024-03-02"), date("2024-03-02"))
+            .await
+            .unwrap();
+        assert_eq!(slots[0].dish_names, names(&["炒青菜", "东坡肉"]));
+
+        let taken = DishStore::rename(&store, "东坡肉", "东坡肉").await;
+        assert!(taken.is_err());
+    }
+
+    #[tokio::test]
+    async fn renaming_an_ingredient_rewrites_recipes_and_stock() {
+        let store = store();
+        seed(
+            &store,
+            &[
+                dish("红烧肉", vec![line("猪肉", "500g")]),
+                dish("炒青菜", vec![line("青菜", "300g")]),
+            ],
+        )
+        .await;
+        let stock = PantryItem {
+            ingredient_name: "猪肉".to_string(),
+            quantity: 1.0,
+            unit: Some("kg".to_string()),
+            purchased_on: None,
+            expires_on: None,
+        };
+        PantryStore::put_many(&store, &[stock]).await.unwrap();
+
+        let rewritten = IngredientStore::rename(&store, "猪肉", "五花肉")
+            .await
+            .unwrap();
+        let rewritten: Vec<(&str, Vec<(&str, &str)>)> = rewritten
+            .iter()
+            .map(|dish| (dish.name.as_str(), recipe(dish)))
+            .collect();
+        assert_eq!(rewritten, [("红烧肉", vec![("五花肉", "500g")])]);
+        let dish = get_dish(&store, "红烧肉").await.unwrap();
+        assert_eq!(recipe(&dish), [("五花肉", "500g")]);
+        let stock = PantryStore::scan(&store).await.unwrap();
+        let stocked: Vec<&str> = stock.iter().map(|s| s.ingredient_name.as_str()).collect();
+        assert_eq!(stocked, ["五花肉"]);
+    }
+
+    #[tokio::test]
+    async fn deleting_ingredients_removes_them_from_recipes() {
+        let store = store();
+        seed(
+            &store,
+            &[
+                dish("红烧肉", vec![line("猪肉", "500g"), line("冰糖", "30g")]),
+                dish("炒青菜", vec![line("青菜", "300g")]),
+            ],
+        )
+        .await;
+
+        let rewritten = IngredientStore::delete_many(&store, &names(&["冰糖"]))
+            .await
+            .unwrap();
+        let rewritten: Vec<(&str, Vec<(&str, &str)>)> = rewritten
+            .iter()
+            .map(|dish| (dish.name.as_str(), recipe(dish)))
+            .collect();
+        assert_eq!(rewritten, [("红烧肉", vec![("猪肉", "500g")])]);
+        let left = IngredientStore::get_many(&store, &names(&["猪肉", "冰糖", "青菜"]))
+            .await
+            .unwrap();
+        let mut left: Vec<&str> = left.iter().map(|i| i.name.as_str()).collect();
+        left.sort_unstable();
+        assert_eq!(left, ["猪肉", "青菜"]);
+        let dish = get_dish(&store, "炒青菜").await.unwrap();
+        assert_eq!(recipe(&dish), [("青菜", "300g")]);
+    }
+}
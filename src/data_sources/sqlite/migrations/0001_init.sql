@@ -0,0 +1,41 @@
+CREATE TABLE ingredients (
+    name TEXT PRIMARY KEY NOT NULL,
+    category TEXT NOT NULL
+);
+
+CREATE TABLE dishes (
+    name TEXT PRIMARY KEY NOT NULL,
+    meal TEXT,
+    spicy REAL,
+    cook_time INTEGER,
+    recipe_link TEXT,
+    serving INTEGER,
+    one_dish INTEGER,
+    soup INTEGER,
+    style TEXT,
+    -- JSON encoded `Photo`
+    photo TEXT,
+    suppressed INTEGER
+);
+
+CREATE TABLE dish_ingredients (
+    dish TEXT NOT NULL REFERENCES dishes (name) ON DELETE CASCADE ON UPDATE CASCADE,
+    position INTEGER NOT NULL,
+    ingredient TEXT NOT NULL REFERENCES ingredients (name) ON UPDATE CASCADE,
+    quantity TEXT NOT NULL,
+    PRIMARY KEY (dish, position)
+);
+
+CREATE INDEX dish_ingredients_ingredient ON dish_ingredients (ingredient);
+
+CREATE TABLE shares (
+    key TEXT PRIMARY KEY NOT NULL,
+    payload TEXT NOT NULL
+);
+
+CREATE TABLE users (
+    user_id TEXT PRIMARY KEY NOT NULL,
+    -- JSON encoded list of role names
+    roles TEXT NOT NULL,
+    hashed_password TEXT NOT NULL
+);
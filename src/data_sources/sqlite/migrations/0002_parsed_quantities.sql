@@ -0,0 +1,3 @@
+ALTER TABLE dish_ingredients ADD COLUMN amount REAL;
+ALTER TABLE dish_ingredients ADD COLUMN unit TEXT;
+ALTER TABLE dish_ingredients ADD COLUMN note TEXT;
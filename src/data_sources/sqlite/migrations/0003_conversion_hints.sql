@@ -0,0 +1,2 @@
+ALTER TABLE ingredients ADD COLUMN density REAL;
+ALTER TABLE ingredients ADD COLUMN piece_weight REAL;
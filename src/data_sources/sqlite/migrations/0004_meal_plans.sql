@@ -0,0 +1,7 @@
+CREATE TABLE meal_plans (
+    date TEXT NOT NULL,
+    meal TEXT NOT NULL,
+    -- JSON array of dish names
+    dishes TEXT NOT NULL,
+    PRIMARY KEY (date, meal)
+);
@@ -0,0 +1,10 @@
+-- The log outlives the dishes in it, as with the other backends, so it does
+-- not reference `dishes` and renames update it explicitly.
+CREATE TABLE cook_log (
+    dish_name TEXT NOT NULL,
+    date TEXT NOT NULL,
+    meal TEXT NOT NULL,
+    PRIMARY KEY (dish_name, date, meal)
+);
+
+CREATE INDEX cook_log_date ON cook_log (date);
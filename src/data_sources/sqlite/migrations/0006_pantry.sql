@@ -0,0 +1,9 @@
+-- Stock outlives its ingredient, as with the other backends, so it does not
+-- reference `ingredients` and renames update it explicitly.
+CREATE TABLE pantry (
+    ingredient_name TEXT PRIMARY KEY,
+    quantity REAL NOT NULL,
+    unit TEXT,
+    purchased_on TEXT,
+    expires_on TEXT
+);
@@ -0,0 +1,355 @@
+use std::{collections::HashSet, fmt, fs::File};
+
+use anyhow::{anyhow, Error};
+use async_graphql::Enum;
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::graphql::{
+    CookEntry, Dish, DishFilter, Ingredient, Meal, MealSlot, Page, PantryItem, Photo,
+    RawRecipeIngredient, ShareableMenu,
+};
+
+#[derive(Clone, Deserialize)]
+pub(crate) struct UserRecord {
+    pub user_id: String,
+    pub roles: Vec<String>,
+    pub hashed_password: String,
+}
+
+/// What a conditional write expects of the item it writes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WriteCondition {
+    /// The item must not exist yet, the write creates it
+    Absent,
+    /// The item must exist, the write updates it
+    Present,
+}
+
+impl WriteCondition {
+    pub(crate) fn holds(self, exists: bool) -> bool {
+        match self {
+            WriteCondition::Absent => !exists,
+            WriteCondition::Present => exists,
+        }
+    }
+}
+
+/// How a batch of conditional writes treats items whose condition does not
+/// hold. A batch listing an item more than once is not written in either
+/// mode.
+#[derive(Enum, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum BatchMode {
+    /// Write nothing unless the condition holds for every item
+    #[default]
+    Atomic,
+    /// Write every item the condition holds for. A write failing part way
+    /// reports the items written before it
+    BestEffort,
+}
+
+impl BatchMode {
+    /// Whether the items whose condition holds are written, given whether
+    /// it holds for each item of the batch.
+    pub(crate) fn writes(self, held: &[bool]) -> bool {
+        self == BatchMode::BestEffort || held.iter().all(|held| *held)
+    }
+    /// Which of `count` items were written, given the result of writing
+    /// them.
+    pub(crate) fn written(self, result: &Result<Vec<bool>, Error>, count: usize) -> Vec<bool> {
+        match result {
+            Ok(held) => {
+                let writes = self.writes(held);
+                held.iter().map(|held| writes && *held).collect()
+            }
+            Err(err) => match err.downcast_ref::<PartialBatch>() {
+                Some(partial) => partial
+                    .held
+                    .iter()
+                    .map(|held| *held == Some(true))
+                    .collect(),
+                None => vec![false; count],
+            },
+        }
+    }
+}
+
+/// Error of a best effort batch that failed part way, some of its items
+/// written already.
+#[derive(Debug)]
+pub(crate) struct PartialBatch {
+    /// Whether the condition held for each item, `None` for the items the
+    /// failure left unwritten
+    pub held: Vec<Option<bool>>,
+    pub error: Error,
+}
+
+impl fmt::Display for PartialBatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "batch cut short: {}", self.error)
+    }
+}
+
+impl std::error::Error for PartialBatch {}
+
+/// Fails unless a `kind` named `from` exists and none named `to` does.
+pub(crate) fn check_rename(
+    kind: &str,
+    from: &str,
+    to: &str,
+    from_exists: bool,
+    to_exists: bool,
+) -> Result<(), Error> {
+    if to.trim().is_empty() {
+        return Err(anyhow!("{} name cannot be empty", kind));
+    }
+    if to == from {
+        return Err(anyhow!("{} {} already has that name", kind, from));
+    }
+    if !from_exists {
+        return Err(anyhow!("cannot find {} {}", kind, from));
+    }
+    if to_exists {
+        return Err(anyhow!("{} {} already exists", kind, to));
+    }
+    Ok(())
+}
+
+/// Replaces `from` in a list of names by `to`, which is then listed once.
+/// Returns whether the list changed.
+pub(crate) fn rename_in(names: &mut Vec<String>, from: &str, to: &str) -> bool {
+    if !names.iter().any(|name| name == from) {
+        return false;
+    }
+    let mut seen = false;
+    names.retain_mut(|name| {
+        if name == from {
+            *name = to.to_string();
+        }
+        let keep = name != to || !seen;
+        seen |= name == to;
+        keep
+    });
+    true
+}
+
+/// The ones of `ingredients` that `recipes` name.
+pub(crate) fn named_in<'a>(
+    ingredients: &[Ingredient],
+    recipes: impl IntoIterator<Item = &'a [RawRecipeIngredient]>,
+) -> Vec<Ingredient> {
+    let named: HashSet<&str> = recipes
+        .into_iter()
+        .flatten()
+        .map(|line| line.name.as_str())
+        .collect();
+    ingredients
+        .iter()
+        .filter(|ingredient| named.contains(ingredient.name.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Changes to one dish. Fields that are `None` stay as they are, fields
+/// that are `Some(None)` are cleared.
+#[derive(Clone, Default)]
+pub(crate) struct DishPatch {
+    pub name: String,
+    pub meal: Option<Option<Meal>>,
+    pub ingredients: Option<Vec<RawRecipeIngredient>>,
+    pub spicy: Option<Option<f32>>,
+    pub cook_time: Option<Option<u32>>,
+    pub recipe_link: Option<Option<String>>,
+    pub serving: Option<Option<u32>>,
+    pub one_dish: Option<Option<bool>>,
+    pub soup: Option<Option<bool>>,
+    pub style: Option<Option<String>>,
+    pub photo: Option<Option<Photo>>,
+    pub suppressed: Option<Option<bool>>,
+}
+
+impl DishPatch {
+    pub(crate) fn apply(&self, dish: &mut Dish) {
+        fn change<T: Clone>(field: &mut Option<T>, value: &Option<Option<T>>) {
+            if let Some(value) = value {
+                field.clone_from(value);
+            }
+        }
+        change(&mut dish.meal, &self.meal);
+        if let Some(ingredients) = &self.ingredients {
+            dish.ingredients.clone_from(ingredients);
+        }
+        change(&mut dish.spicy, &self.spicy);
+        change(&mut dish.cook_time, &self.cook_time);
+        change(&mut dish.recipe_link, &self.recipe_link);
+        change(&mut dish.serving, &self.serving);
+        change(&mut dish.one_dish, &self.one_dish);
+        change(&mut dish.soup, &self.soup);
+        change(&mut dish.style, &self.style);
+        change(&mut dish.photo, &self.photo);
+        change(&mut dish.suppressed, &self.suppressed);
+    }
+}
+
+/// Storage for `todays-menu-dishes`, keyed by dish name.
+#[async_trait::async_trait]
+pub(crate) trait DishStore: Send + Sync {
+    /// Names that do not exist are left out of the result.
+    async fn get_many(&self, names: &[String]) -> Result<Vec<Dish>, Error>;
+    /// One page of at most `limit` dishes matching `filter`, continuing
+    /// after the key returned with the previous page. A page may hold fewer
+    /// items than `limit` even when more follow.
+    async fn scan_page(
+        &self,
+        filter: Option<&DishFilter>,
+        after: Option<String>,
+        limit: Option<usize>,
+    ) -> Result<Page<Dish>, Error>;
+    /// Every dish matching `filter`, following pagination to completion.
+    async fn scan(&self, filter: Option<&DishFilter>) -> Result<Vec<Dish>, Error> {
+        let mut dishes = vec![];
+        let mut after = None;
+        loop {
+            let mut page = self.scan_page(filter, after, None).await?;
+            dishes.append(&mut page.items);
+            match page.next_cursor {
+                Some(next) => after = Some(next),
+                None => return Ok(dishes),
+            }
+        }
+    }
+    async fn count(&self, filter: Option<&DishFilter>) -> Result<usize, Error> {
+        Ok(self.scan(filter).await?.len())
+    }
+    /// Stores every recipe kept in an older format in the current one,
+    /// leaving out dishes changed or deleted meanwhile. Returns the number
+    /// of dishes rewritten.
+    async fn migrate_quantities(&self) -> Result<usize, Error>;
+    /// Upserts dishes, but only when `condition` holds, as `mode` says. A
+    /// dish without `photo` keeps its stored photo. The ones of `new_ingredients` named by the dishes
+    /// written are created with them, unless they exist by then. Returns
+    /// whether `condition` held for each dish, in order.
+    async fn update_many_if(
+        &self,
+        items: &[Dish],
+        condition: WriteCondition,
+        mode: BatchMode,
+        new_ingredients: &[Ingredient],
+    ) -> Result<Vec<bool>, Error>;
+    /// Applies each patch to its dish, as `mode` says when some dishes do
+    /// not exist, creating `new_ingredients` like `update_many_if`.
+    /// Returns whether each dish existed, in order.
+    async fn patch_many(
+        &self,
+        patches: &[DishPatch],
+        mode: BatchMode,
+        new_ingredients: &[Ingredient],
+    ) -> Result<Vec<bool>, Error>;
+    /// Moves the dish from `from` to `to` in one transaction, along with
+    /// its cook log entries and the meal plan slots naming it. Fails when
+    /// `from` does not exist or `to` does.
+    async fn rename(&self, from: &str, to: &str) -> Result<(), Error>;
+    /// Names that do not exist are ignored. Failing after deleting some of
+    /// the dishes gives a `PartialBatch` telling which.
+    async fn delete_many(&self, names: &[String]) -> Result<(), Error>;
+}
+
+/// Storage for `todays-menu-ingredients`, keyed by ingredient name.
+#[async_trait::async_trait]
+pub(crate) trait IngredientStore: Send + Sync {
+    /// Names that do not exist are left out of the result.
+    async fn get_many(&self, names: &[String]) -> Result<Vec<Ingredient>, Error>;
+    /// See `DishStore::scan_page`.
+    async fn scan_page(
+        &self,
+        after: Option<String>,
+        limit: Option<usize>,
+    ) -> Result<Page<Ingredient>, Error>;
+    /// Every ingredient, following pagination to completion.
+    async fn scan(&self) -> Result<Vec<Ingredient>, Error> {
+        let mut ingredients = vec![];
+        let mut after = None;
+        loop {
+            let mut page = self.scan_page(after, None).await?;
+            ingredients.append(&mut page.items);
+            match page.next_cursor {
+                Some(next) => after = Some(next),
+                None => return Ok(ingredients),
+            }
+        }
+    }
+    async fn count(&self) -> Result<usize, Error> {
+        Ok(self.scan().await?.len())
+    }
+    /// Writes ingredients only when `condition` holds, as `mode` says.
+    /// Returns whether it held for each ingredient, in order.
+    async fn update_many_if(
+        &self,
+        items: &[Ingredient],
+        condition: WriteCondition,
+        mode: BatchMode,
+    ) -> Result<Vec<bool>, Error>;
+    /// Moves the ingredient from `from` to `to` in one transaction, along
+    /// with its pantry stock, and rewrites the recipes naming it. Fails when
+    /// `from` does not exist or `to` does. Returns the rewritten dishes.
+    async fn rename(&self, from: &str, to: &str) -> Result<Vec<Dish>, Error>;
+    /// Deletes the ingredients along with the recipe lines naming them, in
+    /// one transaction. Pantry stock is kept. Names that do not exist are
+    /// ignored. Returns the rewritten dishes.
+    async fn delete_many(&self, names: &[String]) -> Result<Vec<Dish>, Error>;
+}
+
+/// Storage for `todays-menu-shares`, keyed by share key.
+#[async_trait::async_trait]
+pub(crate) trait ShareStore: Send + Sync {
+    /// Keys that do not exist are left out of the result.
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<ShareableMenu>, Error>;
+    async fn put(&self, item: &ShareableMenu) -> Result<(), Error>;
+    async fn delete(&self, key: &str) -> Result<(), Error>;
+}
+
+/// Storage for `todays-menu-meal-plans`, keyed by date and meal.
+#[async_trait::async_trait]
+pub(crate) trait MealPlanStore: Send + Sync {
+    /// Slots from `from` to `to`, both included. Slots without dishes are
+    /// left out of the result.
+    async fn get_range(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<MealSlot>, Error>;
+    /// Stores every slot, removing those without dishes.
+    async fn put_many(&self, slots: &[MealSlot]) -> Result<(), Error>;
+}
+
+/// Storage for `todays-menu-cook-log`, keyed by dish name, date and meal.
+#[async_trait::async_trait]
+pub(crate) trait CookLogStore: Send + Sync {
+    /// Every entry for any of `dish_names`.
+    async fn get_for_dishes(&self, dish_names: &[String]) -> Result<Vec<CookEntry>, Error>;
+    /// Entries from `from` to `to`, both included.
+    async fn get_range(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<CookEntry>, Error>;
+    /// Stores every entry. An entry that exists already is left as is.
+    /// Returns the entries that did not exist before.
+    async fn put_many(&self, entries: &[CookEntry]) -> Result<Vec<CookEntry>, Error>;
+}
+
+/// Storage for `todays-menu-pantry`, keyed by ingredient name.
+#[async_trait::async_trait]
+pub(crate) trait PantryStore: Send + Sync {
+    /// Names that are not in stock are left out of the result.
+    async fn get_many(&self, ingredient_names: &[String]) -> Result<Vec<PantryItem>, Error>;
+    async fn scan(&self) -> Result<Vec<PantryItem>, Error>;
+    async fn put_many(&self, items: &[PantryItem]) -> Result<(), Error>;
+    async fn delete_many(&self, ingredient_names: &[String]) -> Result<(), Error>;
+}
+
+/// Storage for `todays-menu-users`, keyed by user id.
+#[async_trait::async_trait]
+pub(crate) trait UserStore: Send + Sync {
+    async fn get(&self, user_id: &str) -> Result<Option<UserRecord>, Error>;
+}
+
+/// Storage for uploaded dish photos.
+#[async_trait::async_trait]
+pub(crate) trait PhotoStore: Send + Sync {
+    async fn put(&self, key: &str, content_type: &str, content: File) -> Result<(), Error>;
+    /// Deleting a photo that does not exist is not an error.
+    async fn delete(&self, key: &str) -> Result<(), Error>;
+}
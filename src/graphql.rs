@@ -1,16 +1,31 @@
+use std::{cmp::Ordering, collections::HashMap, sync::Arc};
+
 use async_graphql::{
-    dataloader::DataLoader, ComplexObject, Context, Enum, Error, InputObject, Object, SimpleObject,
-    Upload,
+    connection::{query, Connection, Edge},
+    dataloader::DataLoader,
+    ComplexObject, Context, Enum, Error, InputObject, MaybeUndefined, Object, OutputType,
+    SimpleObject, Union, Upload,
 };
+use chrono::{Days, Local, NaiveDate};
 use poem::web::headers::{authorization::Bearer, Authorization};
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
-use crate::data_sources::{
-    auth::{Authenticator, Authorizer},
-    dishes::DishLoader,
-    ingredients::IngredientLoader,
-    shares::ShareLoader,
+use crate::{
+    data_sources::{
+        auth::{Authenticator, Authorizer},
+        cook_log::CookLogLoader,
+        dishes::DishLoader,
+        ingredients::IngredientLoader,
+        meal_plans::MealPlanner,
+        pantry::Pantry,
+        search::{DocKind, SearchIndex},
+        shares::ShareLoader,
+        store::{BatchMode, PartialBatch, WriteCondition},
+    },
+    menu,
+    quantity::QuantityRecord,
+    shopping,
 };
 
 pub(crate) struct Query;
@@ -18,13 +33,349 @@ pub(crate) struct Mutation;
 
 #[Object]
 impl Query {
-    async fn dishes(&self, ctx: &Context<'_>) -> Result<Vec<Dish>, Error> {
+    async fn dishes(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<DishFilter>,
+        order_by: Option<DishOrder>,
+    ) -> Result<Vec<Dish>, Error> {
+        let loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+        Ok(loader
+            .loader()
+            .load_all(filter.as_ref(), order_by.as_ref())
+            .await?)
+    }
+    async fn ingredients(
+        &self,
+        ctx: &Context<'_>,
+        order_by: Option<IngredientOrder>,
+    ) -> Result<Vec<Ingredient>, Error> {
+        let loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
+        Ok(loader.loader().load_all(order_by.as_ref()).await?)
+    }
+    /// Page through dishes instead of loading all of them at once
+    async fn dishes_page(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 100, validator(minimum = 1, maximum = 1000))] limit: usize,
+        after: Option<String>,
+        filter: Option<DishFilter>,
+        #[graphql(desc = "Sorted pages load and sort every matching dish again for each page")]
+        order_by: Option<DishOrder>,
+    ) -> Result<Page<Dish>, Error> {
+        let loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+        Ok(loader
+            .loader()
+            .load_page(filter.as_ref(), order_by.as_ref(), after, limit)
+            .await?)
+    }
+    /// Page through ingredients instead of loading all of them at once
+    async fn ingredients_page(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 100, validator(minimum = 1, maximum = 1000))] limit: usize,
+        after: Option<String>,
+        #[graphql(desc = "Sorted pages load and sort every ingredient again for each page")]
+        order_by: Option<IngredientOrder>,
+    ) -> Result<Page<Ingredient>, Error> {
+        let loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
+        Ok(loader
+            .loader()
+            .load_page(order_by.as_ref(), after, limit)
+            .await?)
+    }
+    /// Relay style pagination over dishes, cursors are dish names
+    #[allow(clippy::too_many_arguments)]
+    async fn dishes_connection(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        filter: Option<DishFilter>,
+        #[graphql(desc = "Sorted pages load and sort every matching dish again for each page")]
+        order_by: Option<DishOrder>,
+    ) -> Result<Connection<String, Dish, DishConnectionFields>, Error> {
         let loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
-        Ok(loader.loader().load_all().await?)
+        query(
+            after,
+            before,
+            first,
+            last,
+            |after, before, first, last| async move {
+                let window = loader
+                    .loader()
+                    .load_window(
+                        filter.as_ref(),
+                        order_by.as_ref(),
+                        after,
+                        before,
+                        first,
+                        last,
+                    )
+                    .await?;
+                let mut connection = Connection::with_additional_fields(
+                    window.has_previous_page,
+                    window.has_next_page,
+                    DishConnectionFields { filter },
+                );
+                connection.append(
+                    window
+                        .items
+                        .into_iter()
+                        .map(|item| Edge::new(item.name.clone(), item)),
+                );
+                Ok::<_, Error>(connection)
+            },
+        )
+        .await
     }
-    async fn ingredients(&self, ctx: &Context<'_>) -> Result<Vec<Ingredient>, Error> {
+    /// Relay style pagination over ingredients, cursors are ingredient names
+    async fn ingredients_connection(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        #[graphql(desc = "Sorted pages load and sort every ingredient again for each page")]
+        order_by: Option<IngredientOrder>,
+    ) -> Result<Connection<String, Ingredient, IngredientConnectionFields>, Error> {
         let loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
-        Ok(loader.loader().load_all().await?)
+        query(
+            after,
+            before,
+            first,
+            last,
+            |after, before, first, last| async move {
+                let window = loader
+                    .loader()
+                    .load_window(order_by.as_ref(), after, before, first, last)
+                    .await?;
+                let mut connection = Connection::with_additional_fields(
+                    window.has_previous_page,
+                    window.has_next_page,
+                    IngredientConnectionFields,
+                );
+                connection.append(
+                    window
+                        .items
+                        .into_iter()
+                        .map(|item| Edge::new(item.name.clone(), item)),
+                );
+                Ok::<_, Error>(connection)
+            },
+        )
+        .await
+    }
+    /// Fuzzy search over dish names, styles and recipe ingredients as well
+    /// as ingredient names and categories, best matches first
+    async fn search(
+        &self,
+        ctx: &Context<'_>,
+        query: String,
+        #[graphql(default = 20, validator(minimum = 1, maximum = 100))] limit: usize,
+    ) -> Result<Vec<SearchResult>, Error> {
+        let index = ctx.data_unchecked::<Arc<SearchIndex>>();
+        let hits = index.search(&query, limit).await?;
+        let dish_loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+        let mut dishes = dish_loader
+            .load_many(
+                hits.iter()
+                    .filter(|hit| hit.kind == DocKind::Dish)
+                    .map(|hit| hit.name.clone()),
+            )
+            .await?;
+        let ingredient_loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
+        let mut ingredients = ingredient_loader
+            .load_many(
+                hits.iter()
+                    .filter(|hit| hit.kind == DocKind::Ingredient)
+                    .map(|hit| hit.name.clone()),
+            )
+            .await?;
+        Ok(hits
+            .into_iter()
+            .filter_map(|hit| {
+                let item = match hit.kind {
+                    DocKind::Dish => dishes.remove(&hit.name).map(SearchItem::Dish),
+                    DocKind::Ingredient => {
+                        ingredients.remove(&hit.name).map(SearchItem::Ingredient)
+                    }
+                }?;
+                Some(SearchResult {
+                    score: hit.score,
+                    item,
+                })
+            })
+            .collect())
+    }
+    /// Dishes using any of the `ingredients` at hand, ranked by how much of
+    /// their recipe those cover. With `maxMissing` dishes missing more
+    /// ingredients than that are left out.
+    async fn what_can_i_cook(
+        &self,
+        ctx: &Context<'_>,
+        ingredients: Vec<String>,
+        max_missing: Option<usize>,
+        #[graphql(default = 20, validator(minimum = 1, maximum = 100))] limit: usize,
+    ) -> Result<Vec<CookableDish>, Error> {
+        let index = ctx.data_unchecked::<Arc<SearchIndex>>();
+        let hits = index.cookable(&ingredients, max_missing, limit).await?;
+        let loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+        let mut dishes = loader
+            .load_many(hits.iter().map(|hit| hit.name.clone()))
+            .await?;
+        Ok(hits
+            .into_iter()
+            .filter_map(|hit| {
+                Some(CookableDish {
+                    dish: dishes.remove(&hit.name)?,
+                    coverage: hit.coverage,
+                    matched: hit.matched,
+                    missing: hit.missing,
+                })
+            })
+            .collect())
+    }
+    /// Proposes a menu for one meal from the dishes that are not suppressed
+    async fn suggest_menu(
+        &self,
+        ctx: &Context<'_>,
+        meal: Meal,
+        #[graphql(validator(minimum = 1))] people: u32,
+        #[graphql(default)] constraints: MenuConstraints,
+    ) -> Result<MenuSuggestion, Error> {
+        let loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+        let dishes = loader.loader().load_all(None, None).await?;
+        let recent = match constraints.avoid_recent_days {
+            0 => HashMap::new(),
+            days => {
+                let today = Local::now().date_naive();
+                let from = today
+                    .checked_sub_days(Days::new(days.into()))
+                    .ok_or("avoidRecentDays is too far back")?;
+                let cook_log = ctx.data_unchecked::<DataLoader<CookLogLoader>>();
+                cook_log.loader().last_cooked(from, today).await?
+            }
+        };
+        let seed = constraints.seed.unwrap_or_else(rand::random);
+        let menu = menu::suggest(dishes, meal, people, &constraints, &recent, seed.into());
+        Ok(MenuSuggestion {
+            satisfied: menu.total_servings >= people,
+            dishes: menu.dishes,
+            total_servings: menu.total_servings,
+            total_cook_time: menu.total_cook_time,
+            seed,
+        })
+    }
+    /// Everything needed to cook `dishes`, grouped by ingredient category.
+    /// With `servings` every dish is scaled from its own serving size.
+    /// Masses and volumes are added up in `unitSystem`.
+    async fn shopping_list(
+        &self,
+        ctx: &Context<'_>,
+        dishes: Vec<String>,
+        #[graphql(validator(minimum = 1))] servings: Option<u32>,
+        #[graphql(default_with = "UnitSystem::Metric")] unit_system: UnitSystem,
+    ) -> Result<ShoppingList, Error> {
+        let dish_loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+        let found = dish_loader.load_many(dishes.iter().cloned()).await?;
+        let mut lines = vec![];
+        for dish in dishes.iter().filter_map(|name| found.get(name)) {
+            let factor = servings
+                .and_then(|servings| dish.scale_factor(servings))
+                .unwrap_or(1.0);
+            lines.extend(dish.ingredients.iter().map(|ingredient| shopping::Line {
+                dish: &dish.name,
+                ingredient,
+                factor,
+            }));
+        }
+        let ingredient_loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
+        let ingredients = ingredient_loader
+            .load_many(lines.iter().map(|line| line.ingredient.name.clone()))
+            .await?;
+        Ok(ShoppingList {
+            categories: shopping::shopping_list(&lines, &ingredients, unit_system),
+            missing_dishes: shopping::missing_dishes(&dishes, &found),
+        })
+    }
+    /// Lunch and dinner of every day from `from` to `to`, both included,
+    /// with empty slots for days nothing is planned for. The range spans at
+    /// most 366 days.
+    async fn meal_plan(
+        &self,
+        ctx: &Context<'_>,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<MealSlot>, Error> {
+        let planner = ctx.data_unchecked::<MealPlanner>();
+        Ok(planner.load_range(from, to).await?)
+    }
+    /// Dishes cooked from `from` to `to`, both included, newest first.
+    async fn cook_history(
+        &self,
+        ctx: &Context<'_>,
+        from: NaiveDate,
+        to: NaiveDate,
+        dish_name: Option<String>,
+    ) -> Result<Vec<CookEntry>, Error> {
+        let loader = ctx.data_unchecked::<DataLoader<CookLogLoader>>();
+        Ok(loader.loader().history(from, to, dish_name).await?)
+    }
+    /// Everything in stock, by ingredient name.
+    async fn pantry(&self, ctx: &Context<'_>) -> Result<Vec<PantryItem>, Error> {
+        let pantry = ctx.data_unchecked::<Pantry>();
+        Ok(pantry.load_all().await?)
+    }
+    /// Items in stock that expire within `days` from today, or have
+    /// expired already, the soonest first.
+    async fn expiring_soon(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 3, validator(minimum = 0))] days: i64,
+    ) -> Result<Vec<PantryItem>, Error> {
+        let pantry = ctx.data_unchecked::<Pantry>();
+        let until = u64::try_from(days)
+            .ok()
+            .and_then(|days| Local::now().date_naive().checked_add_days(Days::new(days)))
+            .ok_or("days is too far ahead")?;
+        Ok(pantry.expiring(until).await?)
+    }
+    /// Recipe lines naming ingredients that are not in the ingredient
+    /// table, which `Dish.ingredients` leaves out.
+    async fn dangling_ingredients(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<DanglingIngredient>, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        let dish_loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+        let dishes = dish_loader.loader().load_all(None, None).await?;
+        let mut dangling: Vec<DanglingIngredient> = vec![];
+        for dish in &dishes {
+            for item in &dish.ingredients {
+                match dangling.iter_mut().find(|d| d.name == item.name) {
+                    Some(d) if !d.dishes.contains(&dish.name) => d.dishes.push(dish.name.clone()),
+                    Some(_) => {}
+                    None => dangling.push(DanglingIngredient {
+                        name: item.name.clone(),
+                        dishes: vec![dish.name.clone()],
+                    }),
+                }
+            }
+        }
+        let loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
+        let found = loader
+            .load_many(dangling.iter().map(|d| d.name.clone()))
+            .await?;
+        dangling.retain(|d| !found.contains_key(&d.name));
+        dangling.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(dangling)
     }
     async fn shareable_menu(&self, ctx: &Context<'_>, key: String) -> Result<ShareableMenu, Error> {
         let loader = ctx.data_unchecked::<DataLoader<ShareLoader>>();
@@ -52,52 +403,488 @@ impl Mutation {
             key: Some(key),
         })
     }
+    async fn delete_share(&self, ctx: &Context<'_>, key: String) -> Result<MutationResult, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        let loader = ctx.data_unchecked::<DataLoader<ShareLoader>>();
+        if loader.load_one(key.clone()).await?.is_none() {
+            return Err("cannot find shared menu".into());
+        }
+        loader.loader().delete_one(&key).await?;
+        Ok(MutationResult {
+            success: true,
+            message: Some("deleted one shared menu".to_string()),
+        })
+    }
+    /// Creates dishes. When a name exists already, nothing is written
+    /// unless `mode` is best effort, which writes the other dishes.
     async fn add_new_dishes(
         &self,
         ctx: &Context<'_>,
         dishes: Vec<DishInput>,
-    ) -> Result<MutationResult, Error> {
+        #[graphql(default)] missing_ingredients: MissingIngredients,
+        #[graphql(default)] mode: BatchMode,
+    ) -> Result<BatchMutationResult, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        let names = dishes.iter().map(|d| d.name.clone());
+        if let Some(rejected) = BatchMutationResult::duplicates("added", "dishes", names) {
+            return Ok(rejected);
+        }
+        let recipes = dishes.iter().map(|d| d.ingredients.as_slice());
+        let new_ingredients = check_recipe_ingredients(ctx, recipes, missing_ingredients).await?;
+        let loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+        let result = loader
+            .loader()
+            .write_many(ctx, &dishes, WriteCondition::Absent, mode, &new_ingredients)
+            .await;
+        Ok(BatchMutationResult::new(
+            "added",
+            "dishes",
+            dishes.iter().map(|d| d.name.clone()),
+            result,
+            WriteCondition::Absent,
+            mode,
+        )?)
+    }
+    /// Rewrites the recipes whose quantities are stored as bare text so
+    /// they are stored parsed, leaving the rest of each dish alone. Dishes
+    /// read fine either way, this only saves parsing them again on every
+    /// read.
+    async fn migrate_quantities(&self, ctx: &Context<'_>) -> Result<MutationResult, Error> {
         let auth_header = ctx.data::<Authorization<Bearer>>()?;
         let auth = ctx.data_unchecked::<Authorizer>();
         auth.authorize_mutate(auth_header.token())?;
 
         let loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
-        loader.loader().update_many(ctx, &dishes).await?;
+        let migrated = loader.loader().migrate_quantities().await?;
         Ok(MutationResult {
             success: true,
-            message: Some(format!("added {} dishes", dishes.len())),
+            message: Some(format!("migrated {} dishes", migrated)),
         })
     }
+    /// Replaces dishes. When one does not exist, nothing is written unless
+    /// `mode` is best effort, which writes the other dishes.
     async fn update_dishes(
         &self,
         ctx: &Context<'_>,
         dishes: Vec<DishInput>,
+        #[graphql(default)] missing_ingredients: MissingIngredients,
+        #[graphql(default)] mode: BatchMode,
+    ) -> Result<BatchMutationResult, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        let names = dishes.iter().map(|d| d.name.clone());
+        if let Some(rejected) = BatchMutationResult::duplicates("updated", "dishes", names) {
+            return Ok(rejected);
+        }
+        let recipes = dishes.iter().map(|d| d.ingredients.as_slice());
+        let new_ingredients = check_recipe_ingredients(ctx, recipes, missing_ingredients).await?;
+        let loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+        let result = loader
+            .loader()
+            .write_many(
+                ctx,
+                &dishes,
+                WriteCondition::Present,
+                mode,
+                &new_ingredients,
+            )
+            .await;
+        Ok(BatchMutationResult::new(
+            "updated",
+            "dishes",
+            dishes.iter().map(|d| d.name.clone()),
+            result,
+            WriteCondition::Present,
+            mode,
+        )?)
+    }
+    /// Changes only the fields each patch sets, fields set to null are
+    /// cleared. When a dish does not exist, nothing is written unless
+    /// `mode` is best effort, which patches the other dishes.
+    async fn patch_dishes(
+        &self,
+        ctx: &Context<'_>,
+        dishes: Vec<DishPatchInput>,
+        #[graphql(default)] missing_ingredients: MissingIngredients,
+        #[graphql(default)] mode: BatchMode,
+    ) -> Result<BatchMutationResult, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        let names = dishes.iter().map(|d| d.name.clone());
+        if let Some(rejected) = BatchMutationResult::duplicates("patched", "dishes", names) {
+            return Ok(rejected);
+        }
+        let recipes = dishes
+            .iter()
+            .filter_map(|d| d.ingredients.as_opt_ref().flatten().map(Vec::as_slice));
+        let new_ingredients = check_recipe_ingredients(ctx, recipes, missing_ingredients).await?;
+        let loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+        let result = loader
+            .loader()
+            .patch_many(ctx, &dishes, mode, &new_ingredients)
+            .await;
+        Ok(BatchMutationResult::new(
+            "patched",
+            "dishes",
+            dishes.iter().map(|d| d.name.clone()),
+            result,
+            WriteCondition::Present,
+            mode,
+        )?)
+    }
+    /// Deletes dishes and their photos. Names that do not exist are
+    /// ignored.
+    async fn delete_dishes(
+        &self,
+        ctx: &Context<'_>,
+        names: Vec<String>,
     ) -> Result<MutationResult, Error> {
         let auth_header = ctx.data::<Authorization<Bearer>>()?;
         let auth = ctx.data_unchecked::<Authorizer>();
         auth.authorize_mutate(auth_header.token())?;
 
         let loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
-        loader.loader().update_many(ctx, &dishes).await?;
+        let deleted = loader.loader().delete_many(&names).await?;
         Ok(MutationResult {
             success: true,
-            message: Some(format!("updated {} dishes", dishes.len())),
+            message: Some(format!("deleted {} dishes", deleted.len())),
         })
     }
+    /// Renames a dish. Its cook log and the meal plans naming it follow.
+    async fn rename_dish(
+        &self,
+        ctx: &Context<'_>,
+        from: String,
+        to: String,
+    ) -> Result<MutationResult, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        let loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+        loader.loader().rename(&from, &to).await?;
+        Ok(MutationResult {
+            success: true,
+            message: Some(format!("renamed dish {} to {}", from, to)),
+        })
+    }
+    /// Creates ingredients. When a name exists already, nothing is written
+    /// unless `mode` is best effort, which writes the other ingredients.
     async fn add_new_ingredients(
         &self,
         ctx: &Context<'_>,
         ingredients: Vec<IngredientInput>,
+        #[graphql(default)] mode: BatchMode,
+    ) -> Result<BatchMutationResult, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        let names = ingredients.iter().map(|i| i.name.clone());
+        if let Some(rejected) = BatchMutationResult::duplicates("added", "ingredients", names) {
+            return Ok(rejected);
+        }
+        let loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
+        let result = loader
+            .loader()
+            .write_many(&ingredients, WriteCondition::Absent, mode)
+            .await;
+        Ok(BatchMutationResult::new(
+            "added",
+            "ingredients",
+            ingredients.iter().map(|i| i.name.clone()),
+            result,
+            WriteCondition::Absent,
+            mode,
+        )?)
+    }
+    /// Replaces ingredients. When one does not exist, nothing is written
+    /// unless `mode` is best effort, which writes the other ingredients.
+    async fn update_ingredients(
+        &self,
+        ctx: &Context<'_>,
+        ingredients: Vec<IngredientInput>,
+        #[graphql(default)] mode: BatchMode,
+    ) -> Result<BatchMutationResult, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        let names = ingredients.iter().map(|i| i.name.clone());
+        if let Some(rejected) = BatchMutationResult::duplicates("updated", "ingredients", names) {
+            return Ok(rejected);
+        }
+        let loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
+        let result = loader
+            .loader()
+            .write_many(&ingredients, WriteCondition::Present, mode)
+            .await;
+        Ok(BatchMutationResult::new(
+            "updated",
+            "ingredients",
+            ingredients.iter().map(|i| i.name.clone()),
+            result,
+            WriteCondition::Present,
+            mode,
+        )?)
+    }
+    /// Deletes ingredients. Ingredients that recipes still name are refused
+    /// unless `force` is set, in which case their recipe lines are deleted
+    /// too. Names that do not exist are ignored.
+    async fn delete_ingredients(
+        &self,
+        ctx: &Context<'_>,
+        names: Vec<String>,
+        #[graphql(default = false)] force: bool,
+    ) -> Result<MutationResult, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        if !force {
+            let dish_loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+            let using = dish_loader.loader().load_using(&names).await?;
+            let in_use: Vec<String> = names
+                .iter()
+                .filter_map(|name| {
+                    let dishes: Vec<&str> = using
+                        .iter()
+                        .filter(|dish| dish.ingredients.iter().any(|i| &i.name == name))
+                        .map(|dish| dish.name.as_str())
+                        .collect();
+                    (!dishes.is_empty()).then(|| format!("{} ({})", name, dishes.join(", ")))
+                })
+                .collect();
+            if !in_use.is_empty() {
+                return Err(format!("ingredients used by dishes: {}", in_use.join(", ")).into());
+            }
+        }
+        let loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
+        let (deleted, rewritten) = loader.loader().delete_many(&names).await?;
+        Ok(MutationResult {
+            success: true,
+            message: Some(format!(
+                "deleted {} ingredients, rewrote {} dishes",
+                deleted.len(),
+                rewritten
+            )),
+        })
+    }
+    /// Renames an ingredient. Its pantry stock follows and every recipe
+    /// naming it is rewritten.
+    async fn rename_ingredient(
+        &self,
+        ctx: &Context<'_>,
+        from: String,
+        to: String,
     ) -> Result<MutationResult, Error> {
         let auth_header = ctx.data::<Authorization<Bearer>>()?;
         let auth = ctx.data_unchecked::<Authorizer>();
         auth.authorize_mutate(auth_header.token())?;
 
         let loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
-        loader.loader().update_many(&ingredients).await?;
+        let rewritten = loader.loader().rename(&from, &to).await?;
+        Ok(MutationResult {
+            success: true,
+            message: Some(format!(
+                "renamed ingredient {} to {}, rewrote {} dishes",
+                from, to, rewritten
+            )),
+        })
+    }
+    /// Plans `dishes` for a slot, replacing what was planned there unless
+    /// `append` is set.
+    async fn assign_meal_slot(
+        &self,
+        ctx: &Context<'_>,
+        date: NaiveDate,
+        meal: Meal,
+        dishes: Vec<String>,
+        #[graphql(default)] append: bool,
+    ) -> Result<MutationResult, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        check_dishes_exist(ctx, &dishes).await?;
+        let planner = ctx.data_unchecked::<MealPlanner>();
+        planner.assign(date, meal, dishes, append).await?;
         Ok(MutationResult {
             success: true,
-            message: Some(format!("added {} ingredients", ingredients.len())),
+            message: Some(format!("planned {} for {}", meal_name(meal), date)),
+        })
+    }
+    /// Moves the dishes of slot `from` after those planned in slot `to`.
+    async fn move_meal_slot(
+        &self,
+        ctx: &Context<'_>,
+        from: MealSlotKey,
+        to: MealSlotKey,
+    ) -> Result<MutationResult, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        let planner = ctx.data_unchecked::<MealPlanner>();
+        planner
+            .move_slot((from.date, from.meal), (to.date, to.meal))
+            .await?;
+        Ok(MutationResult {
+            success: true,
+            message: Some(format!(
+                "moved {} of {} to {} of {}",
+                meal_name(from.meal),
+                from.date,
+                meal_name(to.meal),
+                to.date
+            )),
+        })
+    }
+    /// Empties every slot from `from` to `to`, both included, or only
+    /// those for `meal`.
+    async fn clear_meal_slots(
+        &self,
+        ctx: &Context<'_>,
+        from: NaiveDate,
+        to: NaiveDate,
+        meal: Option<Meal>,
+    ) -> Result<MutationResult, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        let planner = ctx.data_unchecked::<MealPlanner>();
+        let cleared = planner.clear(from, to, meal).await?;
+        Ok(MutationResult {
+            success: true,
+            message: Some(format!("cleared {} meal slots", cleared)),
+        })
+    }
+    /// Records that `dishes` were cooked for a meal. Recording a dish twice
+    /// for the same meal has no further effect. Unless `deductStock` is
+    /// false, what the recipes of newly recorded dishes use is taken out of
+    /// the pantry, scaled to `servings` when given.
+    async fn record_cooked(
+        &self,
+        ctx: &Context<'_>,
+        date: NaiveDate,
+        meal: Meal,
+        dishes: Vec<String>,
+        #[graphql(validator(minimum = 1))] servings: Option<u32>,
+        #[graphql(default = true)] deduct_stock: bool,
+    ) -> Result<MutationResult, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        check_dishes_exist(ctx, &dishes).await?;
+        let loader = ctx.data_unchecked::<DataLoader<CookLogLoader>>();
+        let added = loader.loader().record(date, meal, &dishes).await?;
+        let mut message = format!("recorded {} dishes", added.len());
+        if deduct_stock && !added.is_empty() {
+            let dish_loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+            let cooked = dish_loader
+                .load_many(added.into_iter().map(|entry| entry.dish_name))
+                .await?;
+            let mut lines = vec![];
+            for dish in cooked.values() {
+                let factor = servings
+                    .and_then(|servings| dish.scale_factor(servings))
+                    .unwrap_or(1.0);
+                lines.extend(dish.ingredients.iter().map(|line| (line, factor)));
+            }
+            let ingredient_loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
+            let ingredients = ingredient_loader
+                .load_many(lines.iter().map(|(line, _)| line.name.clone()))
+                .await?;
+            let pantry = ctx.data_unchecked::<Pantry>();
+            let deduction = pantry.deduct(&lines, &ingredients).await?;
+            message += &format!(
+                ", deducted stock of {} ingredients",
+                deduction.deducted.len()
+            );
+            if !deduction.unconverted.is_empty() {
+                message += &format!(
+                    ", cannot convert units of {}",
+                    deduction.unconverted.join(", ")
+                );
+            }
+        }
+        Ok(MutationResult {
+            success: true,
+            message: Some(message),
+        })
+    }
+    /// Replaces the stock of each item.
+    async fn set_pantry_items(
+        &self,
+        ctx: &Context<'_>,
+        items: Vec<PantryItemInput>,
+    ) -> Result<MutationResult, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        let names: Vec<String> = items.iter().map(|i| i.ingredient_name.clone()).collect();
+        load_known_ingredients(ctx, &names).await?;
+        let items: Vec<PantryItem> = items.into_iter().map(PantryItem::from).collect();
+        let pantry = ctx.data_unchecked::<Pantry>();
+        pantry.set_many(&items).await?;
+        Ok(MutationResult {
+            success: true,
+            message: Some(format!("set {} pantry items", items.len())),
+        })
+    }
+    /// Adds `delta` in `unit` to the stock of an ingredient, or takes it
+    /// away when negative, converting to the unit it is stocked in. Stock
+    /// never drops below zero. Dates given replace the stored ones.
+    async fn adjust_pantry_stock(
+        &self,
+        ctx: &Context<'_>,
+        ingredient_name: String,
+        delta: f64,
+        unit: Option<String>,
+        purchased_on: Option<NaiveDate>,
+        expires_on: Option<NaiveDate>,
+    ) -> Result<PantryItem, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        let mut ingredients =
+            load_known_ingredients(ctx, std::slice::from_ref(&ingredient_name)).await?;
+        let ingredient = ingredients
+            .remove(&ingredient_name)
+            .expect("known ingredient");
+        let pantry = ctx.data_unchecked::<Pantry>();
+        Ok(pantry
+            .adjust(&ingredient, delta, unit, purchased_on, expires_on)
+            .await?)
+    }
+    async fn remove_pantry_items(
+        &self,
+        ctx: &Context<'_>,
+        ingredient_names: Vec<String>,
+    ) -> Result<MutationResult, Error> {
+        let auth_header = ctx.data::<Authorization<Bearer>>()?;
+        let auth = ctx.data_unchecked::<Authorizer>();
+        auth.authorize_mutate(auth_header.token())?;
+
+        let pantry = ctx.data_unchecked::<Pantry>();
+        pantry.remove_many(&ingredient_names).await?;
+        Ok(MutationResult {
+            success: true,
+            message: Some(format!("removed {} pantry items", ingredient_names.len())),
         })
     }
     async fn login_user(
@@ -111,6 +898,76 @@ impl Mutation {
     }
 }
 
+/// Fails naming every dish in `names` that does not exist.
+async fn check_dishes_exist(ctx: &Context<'_>, names: &[String]) -> Result<(), Error> {
+    let loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+    let found = loader.load_many(names.iter().cloned()).await?;
+    let missing: Vec<&str> = names
+        .iter()
+        .filter(|name| !found.contains_key(*name))
+        .map(String::as_str)
+        .collect();
+    match missing.is_empty() {
+        true => Ok(()),
+        false => Err(format!("unknown dishes: {}", missing.join(", ")).into()),
+    }
+}
+
+/// Loads the ingredients in `names`, failing naming every one that does
+/// not exist.
+async fn load_known_ingredients(
+    ctx: &Context<'_>,
+    names: &[String],
+) -> Result<HashMap<String, Ingredient>, Error> {
+    let loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
+    let found = loader.load_many(names.iter().cloned()).await?;
+    let missing: Vec<&str> = names
+        .iter()
+        .filter(|name| !found.contains_key(*name))
+        .map(String::as_str)
+        .collect();
+    match missing.is_empty() {
+        true => Ok(found),
+        false => Err(format!("unknown ingredients: {}", missing.join(", ")).into()),
+    }
+}
+
+/// Category given to ingredients created for recipes naming them.
+const UNSORTED_CATEGORY: &str = "未分类";
+
+/// Checks that every ingredient named in `recipes` exists. Missing ones
+/// fail the write naming them, or are returned to be created along with
+/// the dishes naming them, as `missing` says.
+async fn check_recipe_ingredients<'a>(
+    ctx: &Context<'_>,
+    recipes: impl Iterator<Item = &'a [RecipeIngredientInput]>,
+    missing: MissingIngredients,
+) -> Result<Vec<Ingredient>, Error> {
+    let mut names: Vec<String> = recipes.flatten().map(|i| i.name.clone()).collect();
+    names.sort();
+    names.dedup();
+    match missing {
+        MissingIngredients::Reject => {
+            load_known_ingredients(ctx, &names).await?;
+            Ok(vec![])
+        }
+        MissingIngredients::Create => {
+            let loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
+            let found = loader.load_many(names.iter().cloned()).await?;
+            Ok(names
+                .into_iter()
+                .filter(|name| !found.contains_key(name))
+                .map(|name| Ingredient {
+                    name,
+                    category: UNSORTED_CATEGORY.to_string(),
+                    density: None,
+                    piece_weight: None,
+                })
+                .collect())
+        }
+    }
+}
+
 #[derive(SimpleObject, Default, Clone, Serialize, Deserialize)]
 #[graphql(rename_fields = "snake_case")]
 #[graphql(complex)]
@@ -133,18 +990,67 @@ pub(crate) struct Dish {
 #[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct RawRecipeIngredient {
     pub name: String,
-    pub quantity: String,
+    pub quantity: Quantity,
+}
+
+/// A recipe quantity parsed from its text, e.g. "200g" or "适量".
+#[derive(SimpleObject, Clone, Serialize, Deserialize)]
+#[serde(from = "QuantityRecord")]
+pub(crate) struct Quantity {
+    pub amount: Option<f64>,
+    pub unit: Option<String>,
+    /// Text that is not part of the amount and unit
+    pub note: Option<String>,
+    /// The quantity as it was entered
+    pub text: String,
 }
 
 #[derive(SimpleObject)]
 pub(crate) struct RecipeIngredient {
     pub ingredient: Ingredient,
+    /// The quantity as it was entered, or scaled to the requested servings
     pub quantity: String,
+    pub parsed_quantity: Quantity,
+    /// Whether `quantity` was scaled. False when no servings were requested,
+    /// the dish has no serving size or the quantity has no amount.
+    pub scaled: bool,
+    /// Whether `quantity` was converted to the requested unit system. False
+    /// for counts and units that are not mass or volume.
+    pub converted: bool,
 }
 
-#[ComplexObject]
 impl Dish {
-    async fn ingredients(&self, ctx: &Context<'_>) -> Result<Vec<RecipeIngredient>, Error> {
+    /// Factor that scales the recipe from its own serving size to
+    /// `servings`, `None` when the dish has no serving size.
+    pub(crate) fn scale_factor(&self, servings: u32) -> Option<f64> {
+        self.serving
+            .filter(|base| *base > 0)
+            .map(|base| servings as f64 / base as f64)
+    }
+}
+
+#[ComplexObject(rename_fields = "snake_case")]
+impl Dish {
+    /// Last day the dish was recorded as cooked
+    async fn last_cooked(&self, ctx: &Context<'_>) -> Result<Option<NaiveDate>, Error> {
+        let loader = ctx.data_unchecked::<DataLoader<CookLogLoader>>();
+        let stats = loader.load_one(self.name.clone()).await?;
+        Ok(stats.and_then(|stats| stats.last_cooked))
+    }
+    async fn times_cooked(&self, ctx: &Context<'_>) -> Result<u32, Error> {
+        let loader = ctx.data_unchecked::<DataLoader<CookLogLoader>>();
+        let stats = loader.load_one(self.name.clone()).await?;
+        Ok(stats.map_or(0, |stats| stats.times_cooked))
+    }
+    /// With `servings` quantities are scaled from the dish's serving size,
+    /// with `unitSystem` converted to its units.
+    async fn ingredients(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(validator(minimum = 1))] servings: Option<u32>,
+        unit_system: Option<UnitSystem>,
+    ) -> Result<Vec<RecipeIngredient>, Error> {
+        let factor = servings.and_then(|servings| self.scale_factor(servings));
         let loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
         let ingredients = loader
             .load_many(
@@ -158,9 +1064,18 @@ impl Dish {
             .ingredients
             .iter()
             .filter(|item| ingredients.contains_key(&item.name))
-            .map(|item| RecipeIngredient {
-                ingredient: ingredients.get(&item.name).unwrap().clone(),
-                quantity: item.quantity.clone(),
+            .map(|item| {
+                let scaled = factor.and_then(|factor| item.quantity.scale(factor));
+                let quantity = scaled.as_ref().unwrap_or(&item.quantity);
+                let converted = unit_system.and_then(|system| quantity.convert(system));
+                let quantity = converted.as_ref().unwrap_or(quantity).clone();
+                RecipeIngredient {
+                    ingredient: ingredients.get(&item.name).unwrap().clone(),
+                    scaled: scaled.is_some(),
+                    converted: converted.is_some(),
+                    quantity: quantity.text.clone(),
+                    parsed_quantity: quantity,
+                }
             })
             .collect())
     }
@@ -170,9 +1085,13 @@ impl Dish {
 pub(crate) struct Ingredient {
     pub name: String,
     pub category: String,
+    /// Grams per millilitre, for converting between volume and mass
+    pub density: Option<f64>,
+    /// Grams per piece, for converting between counts and mass
+    pub piece_weight: Option<f64>,
 }
 
-#[derive(Enum, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Enum, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub(crate) enum Meal {
     #[graphql(name = "lunch")]
     Lunch,
@@ -180,6 +1099,13 @@ pub(crate) enum Meal {
     Dinner,
 }
 
+fn meal_name(meal: Meal) -> &'static str {
+    match meal {
+        Meal::Lunch => "lunch",
+        Meal::Dinner => "dinner",
+    }
+}
+
 #[derive(SimpleObject, Clone, Serialize, Deserialize)]
 pub(crate) struct Photo {
     pub filename: Option<String>,
@@ -187,12 +1113,262 @@ pub(crate) struct Photo {
     pub encoding: Option<String>,
 }
 
+#[derive(SimpleObject)]
+#[graphql(concrete(name = "DishPage", params(Dish)))]
+#[graphql(concrete(name = "IngredientPage", params(Ingredient)))]
+pub(crate) struct Page<T: OutputType> {
+    pub items: Vec<T>,
+    /// Opaque cursor to pass as `after` for the next page, null on the last page
+    pub next_cursor: Option<String>,
+}
+
+pub(crate) struct DishConnectionFields {
+    filter: Option<DishFilter>,
+}
+
+#[Object]
+impl DishConnectionFields {
+    async fn total_count(&self, ctx: &Context<'_>) -> Result<usize, Error> {
+        let loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+        Ok(loader.loader().count(self.filter.as_ref()).await?)
+    }
+}
+
+pub(crate) struct IngredientConnectionFields;
+
+#[Object]
+impl IngredientConnectionFields {
+    async fn total_count(&self, ctx: &Context<'_>) -> Result<usize, Error> {
+        let loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
+        Ok(loader.loader().count().await?)
+    }
+}
+
+#[derive(Union)]
+pub(crate) enum SearchItem {
+    Dish(Dish),
+    Ingredient(Ingredient),
+}
+
+#[derive(SimpleObject)]
+pub(crate) struct SearchResult {
+    /// Relevance between 0 and 1, 1 being every search term in the name
+    pub score: f32,
+    pub item: SearchItem,
+}
+
+#[derive(SimpleObject)]
+pub(crate) struct CookableDish {
+    pub dish: Dish,
+    /// Share of the recipe's ingredients at hand, between 0 and 1
+    pub coverage: f32,
+    /// Recipe ingredients at hand
+    pub matched: Vec<String>,
+    /// Recipe ingredients still needed
+    pub missing: Vec<String>,
+}
+
+#[derive(InputObject)]
+pub(crate) struct MenuConstraints {
+    /// At most this many soups
+    #[graphql(default = 1)]
+    pub max_soups: u32,
+    /// Leave out dishes spicier than this
+    pub max_spicy: Option<f32>,
+    /// Leave out dishes taking longer than this many minutes
+    pub max_cook_time: Option<u32>,
+    /// Total minutes all dishes may take together
+    pub cook_time_budget: Option<u32>,
+    #[graphql(default = 6)]
+    pub max_dishes: usize,
+    /// Dishes cooked within this many days are only picked when nothing
+    /// else fits, those cooked longest ago first
+    #[graphql(default = 7)]
+    pub avoid_recent_days: u32,
+    /// The same seed over the same dishes and recent cooking gives the
+    /// same menu
+    pub seed: Option<u32>,
+}
+
+impl Default for MenuConstraints {
+    fn default() -> Self {
+        MenuConstraints {
+            max_soups: 1,
+            max_spicy: None,
+            max_cook_time: None,
+            cook_time_budget: None,
+            max_dishes: 6,
+            avoid_recent_days: 7,
+            seed: None,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub(crate) struct MenuSuggestion {
+    pub dishes: Vec<Dish>,
+    pub total_servings: u32,
+    pub total_cook_time: u32,
+    /// Whether the dishes serve everyone
+    pub satisfied: bool,
+    /// Seed to get this menu again
+    pub seed: u32,
+}
+
+#[derive(SimpleObject)]
+pub(crate) struct ShoppingList {
+    pub categories: Vec<ShoppingCategory>,
+    /// Requested dishes that do not exist
+    pub missing_dishes: Vec<String>,
+}
+
+#[derive(SimpleObject)]
+pub(crate) struct ShoppingCategory {
+    /// Null for ingredients that are not in the ingredient table
+    pub category: Option<String>,
+    pub items: Vec<ShoppingItem>,
+}
+
+#[derive(SimpleObject)]
+pub(crate) struct ShoppingItem {
+    pub name: String,
+    /// Merged quantities, one per unit
+    pub quantities: Vec<String>,
+    /// Dishes that need this ingredient
+    pub dishes: Vec<String>,
+}
+
 #[derive(SimpleObject)]
 pub(crate) struct MutationResult {
     success: bool,
     message: Option<String>,
 }
 
+/// Result of a mutation that writes or skips each item on its own.
+#[derive(SimpleObject)]
+pub(crate) struct BatchMutationResult {
+    /// Whether every item was written
+    success: bool,
+    message: Option<String>,
+    items: Vec<ItemResult>,
+}
+
+#[derive(SimpleObject)]
+pub(crate) struct ItemResult {
+    name: String,
+    success: bool,
+    /// Why the item was not written
+    reason: Option<String>,
+}
+
+impl BatchMutationResult {
+    /// Reports a batch rejected up front for naming some items more than
+    /// once, which would leave it unclear which of them to write. `None`
+    /// when every name is listed once.
+    fn duplicates(
+        verb: &str,
+        kind: &str,
+        names: impl IntoIterator<Item = String>,
+    ) -> Option<BatchMutationResult> {
+        let names: Vec<String> = names.into_iter().collect();
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for name in &names {
+            *counts.entry(name).or_default() += 1;
+        }
+        if counts.values().all(|count| *count == 1) {
+            return None;
+        }
+        let items: Vec<ItemResult> = names
+            .iter()
+            .map(|name| ItemResult {
+                name: name.clone(),
+                success: false,
+                reason: Some(match counts[name.as_str()] {
+                    1 => "batch not written".to_string(),
+                    _ => "listed more than once".to_string(),
+                }),
+            })
+            .collect();
+        Some(BatchMutationResult {
+            success: false,
+            message: Some(format!("{} 0 of {} {}", verb, items.len(), kind)),
+            items,
+        })
+    }
+    /// Reports which of the named items were written, given the `result`
+    /// of writing them when `condition` holds and the `mode` of the batch.
+    /// A best effort batch cut short reports the items it wrote, failing
+    /// the others; any other error is passed on.
+    fn new(
+        verb: &str,
+        kind: &str,
+        names: impl IntoIterator<Item = String>,
+        result: Result<Vec<bool>, anyhow::Error>,
+        condition: WriteCondition,
+        mode: BatchMode,
+    ) -> Result<BatchMutationResult, anyhow::Error> {
+        let failed = match condition {
+            WriteCondition::Absent => "already exists",
+            WriteCondition::Present => "does not exist",
+        };
+        let (held, writes, unwritten): (Vec<Option<bool>>, _, _) = match result {
+            Ok(held) => {
+                let writes = mode.writes(&held);
+                (held.into_iter().map(Some).collect(), writes, None)
+            }
+            Err(err) => {
+                let partial = err.downcast::<PartialBatch>()?;
+                let unwritten = format!("not written: {}", partial.error);
+                (partial.held, true, Some(unwritten))
+            }
+        };
+        let items: Vec<ItemResult> = names
+            .into_iter()
+            .zip(held)
+            .map(|(name, held)| ItemResult {
+                name,
+                success: writes && held == Some(true),
+                reason: match (writes, held) {
+                    (_, None) => unwritten.clone(),
+                    (_, Some(false)) => Some(failed.to_string()),
+                    (false, Some(true)) => Some("batch not written".to_string()),
+                    (true, Some(true)) => None,
+                },
+            })
+            .collect();
+        let succeeded = items.iter().filter(|item| item.success).count();
+        Ok(BatchMutationResult {
+            success: succeeded == items.len(),
+            message: Some(format!(
+                "{} {} of {} {}",
+                verb,
+                succeeded,
+                items.len(),
+                kind
+            )),
+            items,
+        })
+    }
+}
+
+/// What to do about recipe lines naming ingredients that do not exist.
+#[derive(Enum, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum MissingIngredients {
+    /// Fail the write, naming them
+    #[default]
+    Reject,
+    /// Create them with no details, to be filled in later
+    Create,
+}
+
+/// An ingredient recipes name that is not in the ingredient table.
+#[derive(SimpleObject)]
+pub(crate) struct DanglingIngredient {
+    pub name: String,
+    /// Dishes whose recipe names it
+    pub dishes: Vec<String>,
+}
+
 #[derive(SimpleObject)]
 pub(crate) struct MutationResultWithKey {
     success: bool,
@@ -220,19 +1396,282 @@ pub(crate) struct DishInput {
     pub suppressed: Option<bool>,
 }
 
+/// Changes to one dish. Fields left out stay as they are, fields set to
+/// null are cleared, a null `ingredients` empties the recipe.
+#[derive(InputObject)]
+#[graphql(rename_fields = "snake_case")]
+pub(crate) struct DishPatchInput {
+    pub name: String,
+    pub meal: MaybeUndefined<Meal>,
+    pub ingredients: MaybeUndefined<Vec<RecipeIngredientInput>>,
+    pub spicy: MaybeUndefined<f32>,
+    pub cook_time: MaybeUndefined<u32>,
+    pub recipe_link: MaybeUndefined<String>,
+    pub serving: MaybeUndefined<u32>,
+    pub one_dish: MaybeUndefined<bool>,
+    pub soup: MaybeUndefined<bool>,
+    pub style: MaybeUndefined<String>,
+    pub photo: MaybeUndefined<Upload>,
+    pub suppressed: MaybeUndefined<bool>,
+}
+
+/// Predicates on dishes. Every predicate that is set has to hold, a dish
+/// without a value for the field never matches its predicate. Boolean
+/// flags that are unset on a dish count as false.
+#[derive(InputObject, Clone, Default)]
+#[graphql(rename_fields = "snake_case")]
+pub(crate) struct DishFilter {
+    pub meal: Option<Meal>,
+    pub soup: Option<bool>,
+    pub one_dish: Option<bool>,
+    pub style: Option<String>,
+    pub max_cook_time: Option<u32>,
+    pub min_spicy: Option<f32>,
+    pub max_spicy: Option<f32>,
+    pub suppressed: Option<bool>,
+    /// Name of an ingredient the recipe has to contain
+    pub ingredient: Option<String>,
+    /// Filters that all have to match
+    pub and: Option<Vec<DishFilter>>,
+    /// Filters of which at least one has to match
+    pub or: Option<Vec<DishFilter>>,
+}
+
+impl DishFilter {
+    pub(crate) fn matches(&self, dish: &Dish) -> bool {
+        fn flag(filter: Option<bool>, value: Option<bool>) -> bool {
+            filter.is_none_or(|f| f == value.unwrap_or(false))
+        }
+        self.meal.is_none_or(|m| dish.meal == Some(m))
+            && flag(self.soup, dish.soup)
+            && flag(self.one_dish, dish.one_dish)
+            && self
+                .style
+                .as_ref()
+                .is_none_or(|s| dish.style.as_ref() == Some(s))
+            && self
+                .max_cook_time
+                .is_none_or(|max| dish.cook_time.is_some_and(|t| t <= max))
+            && self
+                .min_spicy
+                .is_none_or(|min| dish.spicy.is_some_and(|s| s >= min))
+            && self
+                .max_spicy
+                .is_none_or(|max| dish.spicy.is_some_and(|s| s <= max))
+            && flag(self.suppressed, dish.suppressed)
+            && self
+                .ingredient
+                .as_ref()
+                .is_none_or(|name| dish.ingredients.iter().any(|i| &i.name == name))
+            && self
+                .and
+                .as_ref()
+                .is_none_or(|filters| filters.iter().all(|f| f.matches(dish)))
+            && self
+                .or
+                .as_ref()
+                .is_none_or(|filters| filters.iter().any(|f| f.matches(dish)))
+    }
+}
+
+/// Units quantities are converted to for display.
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum UnitSystem {
+    /// Grams, kilograms, millilitres and litres
+    Metric,
+    /// Ounces, pounds, teaspoons, tablespoons and cups
+    Imperial,
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SortDirection {
+    Asc,
+    Desc,
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DishSortField {
+    Name,
+    CookTime,
+    Spicy,
+    Serving,
+}
+
+/// Dishes without a value for the sort field come last in either direction,
+/// ties are broken by name.
+#[derive(InputObject)]
+pub(crate) struct DishOrder {
+    pub field: DishSortField,
+    #[graphql(default_with = "SortDirection::Asc")]
+    pub direction: SortDirection,
+}
+
+impl DishOrder {
+    pub(crate) fn compare(&self, a: &Dish, b: &Dish) -> Ordering {
+        let ordering = match self.field {
+            DishSortField::Name => Ordering::Equal,
+            DishSortField::CookTime => compare_present(a.cook_time, b.cook_time, self.direction),
+            DishSortField::Spicy => compare_present(a.spicy, b.spicy, self.direction),
+            DishSortField::Serving => compare_present(a.serving, b.serving, self.direction),
+        };
+        ordering.then_with(|| directed(a.name.cmp(&b.name), self.direction))
+    }
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IngredientSortField {
+    Name,
+    Category,
+}
+
+/// Ties are broken by name.
+#[derive(InputObject)]
+pub(crate) struct IngredientOrder {
+    pub field: IngredientSortField,
+    #[graphql(default_with = "SortDirection::Asc")]
+    pub direction: SortDirection,
+}
+
+impl IngredientOrder {
+    pub(crate) fn compare(&self, a: &Ingredient, b: &Ingredient) -> Ordering {
+        let ordering = match self.field {
+            IngredientSortField::Name => Ordering::Equal,
+            IngredientSortField::Category => directed(a.category.cmp(&b.category), self.direction),
+        };
+        ordering.then_with(|| directed(a.name.cmp(&b.name), self.direction))
+    }
+}
+
+fn directed(ordering: Ordering, direction: SortDirection) -> Ordering {
+    match direction {
+        SortDirection::Asc => ordering,
+        SortDirection::Desc => ordering.reverse(),
+    }
+}
+
+/// Orders present values by `direction` and puts missing ones last.
+fn compare_present<T: PartialOrd>(
+    a: Option<T>,
+    b: Option<T>,
+    direction: SortDirection,
+) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => directed(a.partial_cmp(&b).unwrap_or(Ordering::Equal), direction),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
 #[derive(InputObject, Serialize)]
 pub(crate) struct RecipeIngredientInput {
-    name: String,
-    quantity: String,
+    pub name: String,
+    pub quantity: String,
 }
 
 #[derive(InputObject, Serialize)]
 pub(crate) struct IngredientInput {
     pub name: String,
     pub category: String,
+    /// Grams per millilitre
+    pub density: Option<f64>,
+    /// Grams per piece
+    pub piece_weight: Option<f64>,
+}
+
+/// A dish cooked for one meal.
+#[derive(SimpleObject, Clone, Serialize, Deserialize)]
+#[graphql(complex)]
+pub(crate) struct CookEntry {
+    pub dish_name: String,
+    pub date: NaiveDate,
+    pub meal: Meal,
+}
+
+#[ComplexObject]
+impl CookEntry {
+    /// Null when the dish no longer exists
+    async fn dish(&self, ctx: &Context<'_>) -> Result<Option<Dish>, Error> {
+        let loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+        Ok(loader.load_one(self.dish_name.clone()).await?)
+    }
+}
+
+/// Dishes planned for one meal of one day.
+#[derive(SimpleObject, Clone, Serialize, Deserialize)]
+#[graphql(complex)]
+pub(crate) struct MealSlot {
+    pub date: NaiveDate,
+    pub meal: Meal,
+    /// Names of the planned dishes, in order
+    pub dish_names: Vec<String>,
+}
+
+#[ComplexObject]
+impl MealSlot {
+    /// Planned dishes that still exist, in order.
+    async fn dishes(&self, ctx: &Context<'_>) -> Result<Vec<Dish>, Error> {
+        let loader = ctx.data_unchecked::<DataLoader<DishLoader>>();
+        let mut dishes = loader.load_many(self.dish_names.iter().cloned()).await?;
+        Ok(self
+            .dish_names
+            .iter()
+            .filter_map(|name| dishes.remove(name))
+            .collect())
+    }
+}
+
+#[derive(InputObject)]
+pub(crate) struct MealSlotKey {
+    pub date: NaiveDate,
+    pub meal: Meal,
 }
 
-#[derive(SimpleObject, Clone, Deserialize)]
+/// Stock of one ingredient.
+#[skip_serializing_none]
+#[derive(SimpleObject, Clone, Serialize, Deserialize)]
+#[graphql(complex)]
+pub(crate) struct PantryItem {
+    pub ingredient_name: String,
+    pub quantity: f64,
+    /// Unit the quantity is counted in, pieces when null
+    pub unit: Option<String>,
+    pub purchased_on: Option<NaiveDate>,
+    pub expires_on: Option<NaiveDate>,
+}
+
+#[ComplexObject]
+impl PantryItem {
+    /// Null when the ingredient no longer exists
+    async fn ingredient(&self, ctx: &Context<'_>) -> Result<Option<Ingredient>, Error> {
+        let loader = ctx.data_unchecked::<DataLoader<IngredientLoader>>();
+        Ok(loader.load_one(self.ingredient_name.clone()).await?)
+    }
+}
+
+#[derive(InputObject)]
+pub(crate) struct PantryItemInput {
+    pub ingredient_name: String,
+    #[graphql(validator(minimum = 0))]
+    pub quantity: f64,
+    pub unit: Option<String>,
+    pub purchased_on: Option<NaiveDate>,
+    pub expires_on: Option<NaiveDate>,
+}
+
+impl From<PantryItemInput> for PantryItem {
+    fn from(input: PantryItemInput) -> Self {
+        PantryItem {
+            ingredient_name: input.ingredient_name,
+            quantity: input.quantity,
+            unit: input.unit,
+            purchased_on: input.purchased_on,
+            expires_on: input.expires_on,
+        }
+    }
+}
+
+#[derive(SimpleObject, Clone, Serialize, Deserialize)]
 pub(crate) struct ShareableMenu {
     pub key: String,
     /// Opaque base64 encoded payload
@@ -242,7 +1681,7 @@ pub(crate) struct ShareableMenu {
 #[derive(InputObject, Serialize)]
 pub(crate) struct ShareableMenuInput {
     /// Opaque base64 encoded payload
-    payload: String,
+    pub payload: String,
 }
 
 #[derive(SimpleObject)]
@@ -251,3 +1690,105 @@ pub(crate) struct LoginResult {
     pub message: Option<String>,
     pub token: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn batches_listing_names_once_are_not_rejected() {
+        let result = BatchMutationResult::duplicates("added", "dishes", names(&["a", "b"]));
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn duplicates_reject_the_whole_batch() {
+        let result =
+            BatchMutationResult::duplicates("added", "dishes", names(&["a", "b", "a"])).unwrap();
+        assert!(!result.success);
+        assert_eq!(result.message.as_deref(), Some("added 0 of 3 dishes"));
+        let reasons: Vec<_> = result
+            .items
+            .iter()
+            .map(|item| (item.success, item.reason.as_deref()))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                (false, Some("listed more than once")),
+                (false, Some("batch not written")),
+                (false, Some("listed more than once")),
+            ]
+        );
+    }
+
+    #[test]
+    fn atomic_batches_fail_every_item_when_one_does() {
+        let result = BatchMutationResult::new(
+            "updated",
+            "dishes",
+            names(&["a", "b"]),
+            Ok(vec![true, false]),
+            WriteCondition::Present,
+            BatchMode::Atomic,
+        )
+        .unwrap();
+        assert!(!result.success);
+        assert_eq!(result.message.as_deref(), Some("updated 0 of 2 dishes"));
+        let reasons: Vec<_> = result
+            .items
+            .iter()
+            .map(|item| item.reason.as_deref())
+            .collect();
+        assert_eq!(reasons, [Some("batch not written"), Some("does not exist")]);
+    }
+
+    #[test]
+    fn batches_cut_short_report_the_items_written() {
+        let partial = PartialBatch {
+            held: vec![Some(true), Some(false), None],
+            error: anyhow::anyhow!("throttled"),
+        };
+        let result = BatchMutationResult::new(
+            "added",
+            "dishes",
+            names(&["a", "b", "c"]),
+            Err(partial.into()),
+            WriteCondition::Absent,
+            BatchMode::BestEffort,
+        )
+        .unwrap();
+        assert!(!result.success);
+        assert_eq!(result.message.as_deref(), Some("added 1 of 3 dishes"));
+        let reasons: Vec<_> = result
+            .items
+            .iter()
+            .map(|item| (item.success, item.reason.as_deref()))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                (true, None),
+                (false, Some("already exists")),
+                (false, Some("not written: throttled")),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_errors_are_passed_on() {
+        let result = BatchMutationResult::new(
+            "added",
+            "dishes",
+            names(&["a"]),
+            Err(anyhow::anyhow!("throttled")),
+            WriteCondition::Absent,
+            BatchMode::BestEffort,
+        );
+        assert_eq!(result.err().unwrap().to_string(), "throttled");
+    }
+}
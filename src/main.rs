@@ -1,30 +1,48 @@
 mod data_sources;
 mod graphql;
+mod menu;
+mod quantity;
+mod shopping;
 
-use std::env;
+use std::{env, net::SocketAddr, sync::Arc};
 
 use ::poem::{
-    get, handler, middleware::Cors, post, web::Redirect, EndpointExt, IntoResponse, Route,
+    get, handler, listener::TcpListener, middleware::Cors, post, web::Redirect, EndpointExt,
+    IntoResponse, Request, Route, Server,
 };
 use async_graphql::{dataloader::DataLoader, EmptySubscription, Response, Schema};
 use async_graphql_poem::GraphQLRequest;
 use aws_sdk_dynamodb::Endpoint;
 use data_sources::{
     auth::{Authenticator, Authorizer},
+    cook_log::CookLogLoader,
     dishes::DishLoader,
+    dynamodb::DynamoDbStore,
     ingredients::IngredientLoader,
+    meal_plans::MealPlanner,
+    memory::MemoryStore,
+    pantry::Pantry,
+    photo_dir::DirPhotoStore,
+    s3::S3PhotoStore,
+    search::SearchIndex,
     shares::ShareLoader,
+    sqlite::SqliteStore,
+    store::{
+        CookLogStore, DishStore, IngredientStore, MealPlanStore, PantryStore, PhotoStore,
+        ShareStore, UserStore,
+    },
 };
 use graphql::{Mutation, Query};
 use http::Uri;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use poem::web::{
     headers::{authorization::Bearer, Authorization},
     Data, Json, TypedHeader,
 };
 use poem_lambda::Error;
 
-const DASH_BOARD_URL: &str =
-    "https://studio.apollographql.com/sandbox/explorer?endpoint=http://localhost:3000/graphql";
+const DASH_BOARD_URL: &str = "https://studio.apollographql.com/sandbox/explorer?endpoint=";
+const DEFAULT_SERVER_ADDR: &str = "0.0.0.0:3000";
 
 #[derive(Clone, PartialEq)]
 enum Profile {
@@ -32,14 +50,66 @@ enum Profile {
     Local,
 }
 
+/// Host the dashboard points the explorer at. `None` inside Lambda, where
+/// the request's `Host` header is used instead.
+#[derive(Clone)]
+struct DashboardHost(Option<String>);
+
 #[handler]
-fn dashboard(Data(profile): Data<&Profile>) -> poem::Response {
+fn dashboard(
+    req: &Request,
+    Data(profile): Data<&Profile>,
+    Data(DashboardHost(host)): Data<&DashboardHost>,
+) -> poem::Response {
     match profile {
-        Profile::Local => Redirect::temporary(DASH_BOARD_URL).into_response(),
+        Profile::Local => {
+            let host = host
+                .as_deref()
+                .or_else(|| req.header("host"))
+                .unwrap_or("localhost");
+            Redirect::temporary(dashboard_url(host)).into_response()
+        }
         Profile::Prod => "ok".into_response(),
     }
 }
 
+/// Explorer URL pointed at the GraphQL endpoint on `host`. The host may
+/// come from a request header, so it is encoded rather than trusted to
+/// hold no `&` or `#`.
+fn dashboard_url(host: &str) -> String {
+    let endpoint = format!("http://{}/graphql", host);
+    let endpoint = utf8_percent_encode(&endpoint, NON_ALPHANUMERIC);
+    format!("{}{}", DASH_BOARD_URL, endpoint)
+}
+
+/// Host a browser reaches a server listening on `addr` at. A server
+/// listening on every interface is reached at localhost.
+fn browsable_host(addr: &str) -> String {
+    match addr.parse::<SocketAddr>() {
+        Ok(addr) if addr.ip().is_unspecified() => format!("localhost:{}", addr.port()),
+        _ => addr.to_string(),
+    }
+}
+
+/// Storage backend, selected with the `STORAGE_BACKEND` environment variable.
+#[derive(Clone, PartialEq)]
+enum Backend {
+    DynamoDb,
+    Memory,
+    Sqlite,
+}
+
+struct Stores {
+    dishes: Arc<dyn DishStore>,
+    ingredients: Arc<dyn IngredientStore>,
+    shares: Arc<dyn ShareStore>,
+    meal_plans: Arc<dyn MealPlanStore>,
+    cook_log: Arc<dyn CookLogStore>,
+    pantry: Arc<dyn PantryStore>,
+    users: Arc<dyn UserStore>,
+    photos: Arc<dyn PhotoStore>,
+}
+
 type TodaysMenuSchema = Schema<Query, Mutation, EmptySubscription>;
 
 #[handler]
@@ -66,21 +136,38 @@ async fn main() -> Result<(), Error> {
         }
         _ => Profile::Local,
     };
-    let config = aws_config::load_from_env().await;
-    let dynamodb_local_config = get_dynamodb_config(&profile, &config);
-    let s3_local_config = get_s3_config(&profile, config);
-    let dynamodb = aws_sdk_dynamodb::Client::from_conf(dynamodb_local_config);
-    let s3 = aws_sdk_s3::Client::from_conf(s3_local_config);
-    let dish_loader = DishLoader::new(dynamodb.clone(), s3);
-    let ingredient_loader = IngredientLoader::new(dynamodb.clone());
+    let backend = match env::var("STORAGE_BACKEND").as_deref() {
+        Ok("memory") => Backend::Memory,
+        Ok("sqlite") => Backend::Sqlite,
+        Ok("dynamodb") | Err(_) => Backend::DynamoDb,
+        Ok(b) => return Err(format!("unknown storage backend {}", b).into()),
+    };
+    let stores = match backend {
+        Backend::DynamoDb => dynamodb_stores(&profile).await,
+        Backend::Memory => memory_stores()?,
+        Backend::Sqlite => sqlite_stores()?,
+    };
+    let search_index = Arc::new(SearchIndex::new(
+        stores.dishes.clone(),
+        stores.ingredients.clone(),
+    ));
+    let dish_loader = DishLoader::new(stores.dishes, stores.photos, search_index.clone());
+    let ingredient_loader = IngredientLoader::new(stores.ingredients, search_index.clone());
     let root_key_hex = env::var("AUTH_PRIVATE_KEY")?;
-    let authenticator = Authenticator::new(dynamodb.clone(), &root_key_hex);
+    let authenticator = Authenticator::new(stores.users, &root_key_hex);
     let authorizer = Authorizer::new(&root_key_hex);
-    let share_loader = ShareLoader::new(dynamodb);
+    let share_loader = ShareLoader::new(stores.shares);
+    let meal_planner = MealPlanner::new(stores.meal_plans);
+    let cook_log_loader = CookLogLoader::new(stores.cook_log);
+    let pantry = Pantry::new(stores.pantry);
     let mut schema_builder = Schema::build(Query, Mutation, EmptySubscription)
         .data(DataLoader::new(dish_loader, tokio::spawn))
         .data(DataLoader::new(ingredient_loader, tokio::spawn))
         .data(DataLoader::new(share_loader, tokio::spawn))
+        .data(DataLoader::new(cook_log_loader, tokio::spawn))
+        .data(search_index)
+        .data(meal_planner)
+        .data(pantry)
         .data(authenticator)
         .data(authorizer);
     if profile == Profile::Prod {
@@ -88,10 +175,84 @@ async fn main() -> Result<(), Error> {
     }
     let schema = schema_builder.finish();
     let cors = Cors::new().allow_origin(env::var("CORS_ORIGIN")?);
+    let server_addr = server_addr();
+    let dashboard_host = DashboardHost(server_addr.as_deref().map(browsable_host));
     let app = Route::new()
-        .at("/", get(dashboard.data(profile)))
+        .at("/", get(dashboard.data(profile).data(dashboard_host)))
         .at("/graphql", post(index.data(schema)).with(cors));
-    poem_lambda::run(app).await
+    match server_addr {
+        Some(addr) => Ok(Server::new(TcpListener::bind(addr)).run(app).await?),
+        None => poem_lambda::run(app).await,
+    }
+}
+
+/// Address to serve on as a standalone HTTP server, from `--serve [addr]`
+/// or `SERVER_ADDR`. Without either the app runs inside Lambda.
+fn server_addr() -> Option<String> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--serve" {
+            return Some(
+                args.next()
+                    .unwrap_or_else(|| DEFAULT_SERVER_ADDR.to_string()),
+            );
+        }
+    }
+    env::var("SERVER_ADDR").ok()
+}
+
+async fn dynamodb_stores(profile: &Profile) -> Stores {
+    let config = aws_config::load_from_env().await;
+    let dynamodb_local_config = get_dynamodb_config(profile, &config);
+    let s3_local_config = get_s3_config(profile, config);
+    let dynamodb = aws_sdk_dynamodb::Client::from_conf(dynamodb_local_config);
+    let s3 = aws_sdk_s3::Client::from_conf(s3_local_config);
+    let store = Arc::new(DynamoDbStore::new(dynamodb));
+    Stores {
+        dishes: store.clone(),
+        ingredients: store.clone(),
+        shares: store.clone(),
+        meal_plans: store.clone(),
+        cook_log: store.clone(),
+        pantry: store.clone(),
+        users: store,
+        photos: Arc::new(S3PhotoStore::new(s3)),
+    }
+}
+
+/// Tables start empty unless `SEED_FILE` points at a JSON seed file.
+fn memory_stores() -> Result<Stores, Error> {
+    let store = Arc::new(match env::var("SEED_FILE") {
+        Ok(path) => MemoryStore::from_seed_file(path)?,
+        Err(_) => MemoryStore::new(),
+    });
+    Ok(Stores {
+        dishes: store.clone(),
+        ingredients: store.clone(),
+        shares: store.clone(),
+        meal_plans: store.clone(),
+        cook_log: store.clone(),
+        pantry: store.clone(),
+        users: store.clone(),
+        photos: store,
+    })
+}
+
+/// Database at `SQLITE_PATH` and photos under `PHOTO_DIR`.
+fn sqlite_stores() -> Result<Stores, Error> {
+    let path = env::var("SQLITE_PATH").unwrap_or_else(|_| "todays-menu.db".to_string());
+    let photo_dir = env::var("PHOTO_DIR").unwrap_or_else(|_| "photos".to_string());
+    let store = Arc::new(SqliteStore::open(path)?);
+    Ok(Stores {
+        dishes: store.clone(),
+        ingredients: store.clone(),
+        shares: store.clone(),
+        meal_plans: store.clone(),
+        cook_log: store.clone(),
+        pantry: store.clone(),
+        users: store,
+        photos: Arc::new(DirPhotoStore::new(photo_dir)?),
+    })
 }
 
 fn get_s3_config(profile: &Profile, config: aws_config::Config) -> aws_sdk_s3::Config {
@@ -113,3 +274,30 @@ fn get_dynamodb_config(profile: &Profile, config: &aws_config::Config) -> aws_sd
         Profile::Prod => aws_sdk_dynamodb::config::Builder::from(config).build(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{browsable_host, dashboard_url};
+
+    #[test]
+    fn unspecified_addresses_are_reached_at_localhost() {
+        assert_eq!(browsable_host("0.0.0.0:8080"), "localhost:8080");
+        assert_eq!(browsable_host("[::]:3000"), "localhost:3000");
+    }
+
+    #[test]
+    fn other_addresses_are_kept() {
+        assert_eq!(browsable_host("127.0.0.1:3999"), "127.0.0.1:3999");
+        assert_eq!(browsable_host("example.com:80"), "example.com:80");
+    }
+
+    #[test]
+    fn dashboard_endpoint_is_encoded() {
+        assert_eq!(
+            dashboard_url("localhost:3000"),
+            "https://studio.apollographql.com/sandbox/explorer?endpoint=\
+             http%3A%2F%2Flocalhost%3A3000%2Fgraphql"
+        );
+        assert!(dashboard_url("evil&x=1#y").ends_with("evil%26x%3D1%23y%2Fgraphql"));
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use crate::graphql::{Dish, Meal, MenuConstraints};
+
+/// Dishes picked for one meal and how they add up.
+pub(crate) struct Menu {
+    pub dishes: Vec<Dish>,
+    pub total_servings: u32,
+    pub total_cook_time: u32,
+}
+
+/// Picks dishes for `people` from `dishes` in a random order fixed by
+/// `seed`. Dishes without `serving` count as one serving, dishes without
+/// `cook_time` or `spicy` as taking no time and not being spicy.
+///
+/// Dishes in `recent`, mapped to when they were cooked last, are moved
+/// behind all others, the longest ago first. A first pass takes at most one
+/// dish per style so the menu stays varied, a second pass fills up remaining
+/// servings from the styles already used.
+pub(crate) fn suggest(
+    dishes: Vec<Dish>,
+    meal: Meal,
+    people: u32,
+    constraints: &MenuConstraints,
+    recent: &HashMap<String, NaiveDate>,
+    seed: u64,
+) -> Menu {
+    let mut candidates: Vec<Dish> = dishes
+        .into_iter()
+        .filter(|dish| !dish.suppressed.unwrap_or(false))
+        .filter(|dish| dish.meal.is_none_or(|m| m == meal))
+        .filter(|dish| {
+            constraints
+                .max_spicy
+                .is_none_or(|max| dish.spicy.unwrap_or(0.0) <= max)
+        })
+        .filter(|dish| {
+            constraints
+                .max_cook_time
+                .is_none_or(|max| dish.cook_time.unwrap_or(0) <= max)
+        })
+        .collect();
+    // Scans come back in no particular order, sort first so the seed alone
+    // decides the outcome
+    candidates.sort_by(|a, b| a.name.cmp(&b.name));
+    candidates.shuffle(&mut StdRng::seed_from_u64(seed));
+    // Stable, so dishes not cooked recently keep their shuffled order
+    candidates.sort_by_key(|dish| recent.get(&dish.name).copied());
+
+    let mut menu = Menu {
+        dishes: vec![],
+        total_servings: 0,
+        total_cook_time: 0,
+    };
+    let mut styles = vec![];
+    for vary_styles in [true, false] {
+        let mut rest = vec![];
+        for dish in candidates {
+            if menu.total_servings >= people || menu.dishes.len() >= constraints.max_dishes {
+                break;
+            }
+            let style_taken = dish.style.as_ref().is_some_and(|s| styles.contains(s));
+            if (vary_styles && style_taken) || !fits(&menu, &dish, constraints) {
+                rest.push(dish);
+                continue;
+            }
+            menu.total_servings += dish.serving.unwrap_or(1);
+            menu.total_cook_time += dish.cook_time.unwrap_or(0);
+            styles.extend(dish.style.clone());
+            menu.dishes.push(dish);
+        }
+        candidates = rest;
+    }
+    menu
+}
+
+fn fits(menu: &Menu, dish: &Dish, constraints: &MenuConstraints) -> bool {
+    let soups = menu
+        .dishes
+        .iter()
+        .filter(|d| d.soup.unwrap_or(false))
+        .count() as u32;
+    let one_dishes = menu
+        .dishes
+        .iter()
+        .filter(|d| d.one_dish.unwrap_or(false))
+        .count();
+    (!dish.soup.unwrap_or(false) || soups < constraints.max_soups)
+        // A one-dish meal is a complete meal, one of them is enough
+        && (!dish.one_dish.unwrap_or(false) || one_dishes == 0)
+        && constraints
+            .cook_time_budget
+            .is_none_or(|budget| menu.total_cook_time + dish.cook_time.unwrap_or(0) <= budget)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dish(name: &str) -> Dish {
+        Dish {
+            name: name.to_string(),
+            serving: Some(1),
+            ..Dish::default()
+        }
+    }
+
+    fn names(menu: &Menu) -> Vec<&str> {
+        let mut names: Vec<&str> = menu.dishes.iter().map(|d| d.name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    fn suggest_for(dishes: Vec<Dish>, people: u32, constraints: &MenuConstraints) -> Menu {
+        suggest(
+            dishes,
+            Meal::Dinner,
+            people,
+            constraints,
+            &HashMap::new(),
+            7,
+        )
+    }
+
+    #[test]
+    fn same_seed_gives_same_menu() {
+        let dishes: Vec<Dish> = (0..10).map(|i| dish(&format!("d{}", i))).collect();
+        let mut reversed = dishes.clone();
+        reversed.reverse();
+        let constraints = MenuConstraints::default();
+        let a = suggest_for(dishes, 3, &constraints);
+        let b = suggest_for(reversed, 3, &constraints);
+        assert_eq!(names(&a), names(&b));
+        assert_eq!(a.total_servings, 3);
+    }
+
+    #[test]
+    fn leaves_out_dishes_that_do_not_qualify() {
+        let dishes = vec![
+            Dish {
+                suppressed: Some(true),
+                ..dish("suppressed")
+            },
+            Dish {
+                meal: Some(Meal::Lunch),
+                ..dish("lunch")
+            },
+            Dish {
+                spicy: Some(3.0),
+                ..dish("spicy")
+            },
+            Dish {
+                cook_time: Some(90),
+                ..dish("slow")
+            },
+            dish("ok"),
+        ];
+        let constraints = MenuConstraints {
+            max_spicy: Some(1.0),
+            max_cook_time: Some(30),
+            ..MenuConstraints::default()
+        };
+        assert_eq!(names(&suggest_for(dishes, 5, &constraints)), ["ok"]);
+    }
+
+    #[test]
+    fn recently_cooked_dishes_come_last() {
+        let dishes = vec![dish("a"), dish("b"), dish("c")];
+        let today = NaiveDate::from_ymd_opt(2026, 10, 18).unwrap();
+        let recent = HashMap::from([
+            ("a".to_string(), today),
+            ("b".to_string(), today.pred_opt().unwrap()),
+        ]);
+        let constraints = MenuConstraints::default();
+        let menu = suggest(dishes.clone(), Meal::Dinner, 1, &constraints, &recent, 1);
+        assert_eq!(names(&menu), ["c"]);
+        let menu = suggest(dishes, Meal::Dinner, 2, &constraints, &recent, 1);
+        assert_eq!(names(&menu), ["b", "c"]);
+    }
+
+    #[test]
+    fn varies_styles_before_repeating_them() {
+        let styled = |name: &str, style: &str| Dish {
+            style: Some(style.to_string()),
+            ..dish(name)
+        };
+        let dishes = vec![
+            styled("a1", "川菜"),
+            styled("a2", "川菜"),
+            styled("a3", "川菜"),
+            styled("b1", "粤菜"),
+        ];
+        let menu = suggest_for(dishes, 2, &MenuConstraints::default());
+        let styles: Vec<_> = menu.dishes.iter().map(|d| d.style.clone()).collect();
+        assert!(styles.contains(&Some("粤菜".to_string())));
+        assert!(styles.contains(&Some("川菜".to_string())));
+    }
+
+    #[test]
+    fn respects_soup_one_dish_and_budget_limits() {
+        let dishes = vec![
+            Dish {
+                soup: Some(true),
+                ..dish("soup1")
+            },
+            Dish {
+                soup: Some(true),
+                ..dish("soup2")
+            },
+            Dish {
+                one_dish: Some(true),
+                ..dish("rice1")
+            },
+            Dish {
+                one_dish: Some(true),
+                ..dish("rice2")
+            },
+            Dish {
+                cook_time: Some(50),
+                ..dish("long")
+            },
+        ];
+        let constraints = MenuConstraints {
+            cook_time_budget: Some(40),
+            ..MenuConstraints::default()
+        };
+        let menu = suggest_for(dishes, 10, &constraints);
+        let soups = menu.dishes.iter().filter(|d| d.soup == Some(true)).count();
+        let one_dishes = menu
+            .dishes
+            .iter()
+            .filter(|d| d.one_dish == Some(true))
+            .count();
+        assert_eq!((soups, one_dishes), (1, 1));
+        assert!(!names(&menu).contains(&"long"));
+        assert!(menu.total_cook_time <= 40);
+    }
+
+    #[test]
+    fn stops_at_max_dishes() {
+        let dishes: Vec<Dish> = (0..10).map(|i| dish(&format!("d{}", i))).collect();
+        let constraints = MenuConstraints {
+            max_dishes: 2,
+            ..MenuConstraints::default()
+        };
+        assert_eq!(suggest_for(dishes, 10, &constraints).dishes.len(), 2);
+    }
+}
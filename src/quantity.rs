@@ -0,0 +1,470 @@
+use serde::Deserialize;
+
+use crate::graphql::{Ingredient, Quantity, UnitSystem};
+
+/// Chinese measure words, longest first so "千克" wins over "克".
+const CJK_UNITS: &[&str] = &[
+    "千克", "公斤", "毫升", "汤匙", "茶匙", "克", "斤", "两", "升", "个", "只", "根", "片", "颗",
+    "勺", "杯", "块", "瓣", "把", "条", "碗", "盒", "包", "袋", "棵", "朵", "头", "张", "粒", "滴",
+];
+
+/// Separators of ranges such as "2-3个" or "2到3个".
+const RANGE_SEPARATORS: &[char] = &['-', '–', '~', '～', '到', '至'];
+
+const VULGAR_FRACTIONS: &[(char, f64)] = &[
+    ('½', 1.0 / 2.0),
+    ('⅓', 1.0 / 3.0),
+    ('⅔', 2.0 / 3.0),
+    ('¼', 1.0 / 4.0),
+    ('¾', 3.0 / 4.0),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Dimension {
+    /// Measured in grams
+    Mass,
+    /// Measured in millilitres
+    Volume,
+    /// Measured in pieces
+    Count,
+}
+
+/// Known units with their size in grams, millilitres or pieces. Units are
+/// matched ignoring ASCII case.
+const UNITS: &[(&str, Dimension, f64)] = &[
+    ("g", Dimension::Mass, 1.0),
+    ("gram", Dimension::Mass, 1.0),
+    ("grams", Dimension::Mass, 1.0),
+    ("克", Dimension::Mass, 1.0),
+    ("kg", Dimension::Mass, 1000.0),
+    ("kilogram", Dimension::Mass, 1000.0),
+    ("kilograms", Dimension::Mass, 1000.0),
+    ("千克", Dimension::Mass, 1000.0),
+    ("公斤", Dimension::Mass, 1000.0),
+    ("斤", Dimension::Mass, 500.0),
+    ("两", Dimension::Mass, 50.0),
+    ("oz", Dimension::Mass, OUNCE),
+    ("ounce", Dimension::Mass, OUNCE),
+    ("ounces", Dimension::Mass, OUNCE),
+    ("lb", Dimension::Mass, POUND),
+    ("lbs", Dimension::Mass, POUND),
+    ("pound", Dimension::Mass, POUND),
+    ("pounds", Dimension::Mass, POUND),
+    ("ml", Dimension::Volume, 1.0),
+    ("毫升", Dimension::Volume, 1.0),
+    ("l", Dimension::Volume, 1000.0),
+    ("liter", Dimension::Volume, 1000.0),
+    ("liters", Dimension::Volume, 1000.0),
+    ("litre", Dimension::Volume, 1000.0),
+    ("litres", Dimension::Volume, 1000.0),
+    ("升", Dimension::Volume, 1000.0),
+    ("tsp", Dimension::Volume, TEASPOON),
+    ("teaspoon", Dimension::Volume, TEASPOON),
+    ("teaspoons", Dimension::Volume, TEASPOON),
+    ("茶匙", Dimension::Volume, TEASPOON),
+    ("tbsp", Dimension::Volume, TABLESPOON),
+    ("tablespoon", Dimension::Volume, TABLESPOON),
+    ("tablespoons", Dimension::Volume, TABLESPOON),
+    ("汤匙", Dimension::Volume, TABLESPOON),
+    ("勺", Dimension::Volume, TABLESPOON),
+    ("cup", Dimension::Volume, CUP),
+    ("cups", Dimension::Volume, CUP),
+    ("杯", Dimension::Volume, CUP),
+    ("piece", Dimension::Count, 1.0),
+    ("pieces", Dimension::Count, 1.0),
+    ("pc", Dimension::Count, 1.0),
+    ("pcs", Dimension::Count, 1.0),
+    ("个", Dimension::Count, 1.0),
+    ("只", Dimension::Count, 1.0),
+    ("颗", Dimension::Count, 1.0),
+];
+
+const OUNCE: f64 = 28.349523125;
+const POUND: f64 = 453.59237;
+const TEASPOON: f64 = 5.0;
+const TABLESPOON: f64 = 15.0;
+const CUP: f64 = 240.0;
+
+/// Dimension and size of `unit`. An amount without unit counts pieces.
+pub(crate) fn unit_size(unit: Option<&str>) -> Option<(Dimension, f64)> {
+    match unit {
+        None => Some((Dimension::Count, 1.0)),
+        Some(unit) => UNITS
+            .iter()
+            .find(|(name, _, _)| name.eq_ignore_ascii_case(unit))
+            .map(|(_, dimension, size)| (*dimension, *size)),
+    }
+}
+
+/// Converts `amount` from one unit to another. Masses, volumes and counts
+/// convert into each other through the ingredient's density and piece
+/// weight. `None` when the units cannot be converted.
+pub(crate) fn convert_amount(
+    amount: f64,
+    from: Option<&str>,
+    to: Option<&str>,
+    ingredient: Option<&Ingredient>,
+) -> Option<f64> {
+    let same_unit = match (from, to) {
+        (Some(from), Some(to)) => from.eq_ignore_ascii_case(to),
+        (from, to) => from == to,
+    };
+    if same_unit {
+        return Some(amount);
+    }
+    let (from_dimension, from_size) = unit_size(from)?;
+    let (to_dimension, to_size) = unit_size(to)?;
+    let base = amount * from_size;
+    if from_dimension == to_dimension {
+        return Some(base / to_size);
+    }
+    // Grams per millilitre or per piece
+    let grams_per = |dimension| {
+        match dimension {
+            Dimension::Mass => Some(1.0),
+            Dimension::Volume => ingredient?.density,
+            Dimension::Count => ingredient?.piece_weight,
+        }
+        .filter(|grams: &f64| *grams > 0.0)
+    };
+    let grams = base * grams_per(from_dimension)?;
+    Some(grams / grams_per(to_dimension)? / to_size)
+}
+
+/// The unit of `system` that an amount of `base` grams or millilitres reads
+/// best in, with its size. `None` for counts, which have no such unit.
+pub(crate) fn display_unit(
+    dimension: Dimension,
+    base: f64,
+    system: UnitSystem,
+) -> Option<(&'static str, f64)> {
+    Some(match (dimension, system) {
+        (Dimension::Mass, UnitSystem::Metric) if base >= 1000.0 => ("kg", 1000.0),
+        (Dimension::Mass, UnitSystem::Metric) => ("g", 1.0),
+        (Dimension::Mass, UnitSystem::Imperial) if base >= POUND => ("lb", POUND),
+        (Dimension::Mass, UnitSystem::Imperial) => ("oz", OUNCE),
+        (Dimension::Volume, UnitSystem::Metric) if base >= 1000.0 => ("l", 1000.0),
+        (Dimension::Volume, UnitSystem::Metric) => ("ml", 1.0),
+        // A quarter cup and more reads better in cups
+        (Dimension::Volume, UnitSystem::Imperial) if base >= CUP / 4.0 => ("cup", CUP),
+        (Dimension::Volume, UnitSystem::Imperial) if base >= TABLESPOON => ("tbsp", TABLESPOON),
+        (Dimension::Volume, UnitSystem::Imperial) => ("tsp", TEASPOON),
+        (Dimension::Count, _) => return None,
+    })
+}
+
+/// A recipe quantity as stored. Records written before quantities were
+/// parsed hold the bare text, they are parsed when read.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum QuantityRecord {
+    Text(String),
+    Parsed {
+        amount: Option<f64>,
+        unit: Option<String>,
+        note: Option<String>,
+        text: String,
+    },
+}
+
+impl From<QuantityRecord> for Quantity {
+    fn from(record: QuantityRecord) -> Self {
+        match record {
+            QuantityRecord::Text(text) => Quantity::parse(&text),
+            // Ranges used to be parsed as their first amount
+            QuantityRecord::Parsed { text, .. } if is_range(&text) => Quantity::parse(&text),
+            QuantityRecord::Parsed {
+                amount,
+                unit,
+                note,
+                text,
+            } => Quantity {
+                amount,
+                unit,
+                note,
+                text,
+            },
+        }
+    }
+}
+
+impl Quantity {
+    /// Parses notations such as "200g", "1/2 cup", "1 ½ tbsp" or "半个".
+    /// Text after the unit becomes the note, text without a leading amount
+    /// such as "适量" is a note on its own, and so is a range such as
+    /// "2-3个", which has no single amount to scale.
+    pub(crate) fn parse(text: &str) -> Quantity {
+        let trimmed = text.trim();
+        let (amount, unit, note) = match split_amount(trimmed) {
+            Some(_) if is_range(trimmed) => (None, None, trimmed),
+            Some((amount, rest)) => {
+                let (unit, note) = split_unit(rest);
+                (Some(amount), unit, note)
+            }
+            None => (None, None, trimmed),
+        };
+        Quantity {
+            amount,
+            unit: unit.map(str::to_string),
+            note: (!note.is_empty()).then(|| note.to_string()),
+            text: text.to_string(),
+        }
+    }
+
+    /// The quantity multiplied by `factor`, with its text rewritten to
+    /// match. `None` when there is no amount to multiply.
+    pub(crate) fn scale(&self, factor: f64) -> Option<Quantity> {
+        let amount = self.amount? * factor;
+        Some(self.with_amount(amount, self.unit.clone()))
+    }
+
+    /// The quantity in units of `system`, with its text rewritten to match.
+    /// `None` without an amount or when the unit is not a mass or volume.
+    pub(crate) fn convert(&self, system: UnitSystem) -> Option<Quantity> {
+        let (dimension, size) = unit_size(self.unit.as_deref())?;
+        let base = self.amount? * size;
+        let (unit, size) = display_unit(dimension, base, system)?;
+        Some(self.with_amount(base / size, Some(unit.to_string())))
+    }
+
+    fn with_amount(&self, amount: f64, unit: Option<String>) -> Quantity {
+        let mut text = format_quantity(amount, unit.as_deref());
+        if let Some(note) = &self.note {
+            text = format!("{} {}", text, note);
+        }
+        Quantity {
+            amount: Some(amount),
+            unit,
+            note: self.note.clone(),
+            text,
+        }
+    }
+}
+
+/// Whether `text` starts with two amounts joined by a range separator.
+fn is_range(text: &str) -> bool {
+    let Some((_, rest)) = split_amount(text.trim()) else {
+        return false;
+    };
+    rest.trim_start()
+        .strip_prefix(RANGE_SEPARATORS)
+        .and_then(|rest| split_amount(rest.trim_start()))
+        .is_some()
+}
+
+fn split_amount(text: &str) -> Option<(f64, &str)> {
+    if let Some(rest) = text.strip_prefix('半') {
+        return Some((0.5, rest));
+    }
+    if let Some(found) = fraction(text) {
+        return Some(found);
+    }
+    let (whole, rest) = decimal(text)?;
+    // Mixed numbers such as "1 1/2" or "1½"
+    match fraction(rest.trim_start()) {
+        Some((part, rest)) if part < 1.0 => Some((whole + part, rest)),
+        _ => Some((whole, rest)),
+    }
+}
+
+fn decimal(text: &str) -> Option<(f64, &str)> {
+    let end = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let amount: f64 = text[..end].parse().ok()?;
+    amount.is_finite().then(|| (amount, &text[end..]))
+}
+
+fn fraction(text: &str) -> Option<(f64, &str)> {
+    let first = text.chars().next()?;
+    if let Some((_, value)) = VULGAR_FRACTIONS.iter().find(|(c, _)| *c == first) {
+        return Some((*value, &text[first.len_utf8()..]));
+    }
+    let (numerator, rest) = decimal(text)?;
+    let (denominator, rest) = decimal(rest.strip_prefix('/')?)?;
+    (denominator != 0.0).then(|| (numerator / denominator, rest))
+}
+
+/// Splits the text after an amount into unit and note.
+fn split_unit(text: &str) -> (Option<&str>, &str) {
+    let text = text.trim_start();
+    let end = match text.find(|c: char| !c.is_ascii_alphabetic()) {
+        Some(0) => CJK_UNITS
+            .iter()
+            .find(|unit| text.starts_with(*unit))
+            .map(|unit| unit.len())
+            .or_else(|| {
+                text.starts_with(char::is_alphabetic)
+                    .then(|| text.find(char::is_whitespace).unwrap_or(text.len()))
+            }),
+        Some(end) => Some(end),
+        None => Some(text.len()),
+    };
+    match end {
+        Some(0) | None => (None, text.trim()),
+        Some(end) => (Some(&text[..end]), text[end..].trim()),
+    }
+}
+
+/// Formats an amount with at most two decimals and no trailing zeros.
+fn format_amount(amount: f64) -> String {
+    let text = format!("{:.2}", amount);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Formats an amount with its unit, spaced for words like "cup" but not
+/// for symbols like "g" or measure words like "个".
+pub(crate) fn format_quantity(amount: f64, unit: Option<&str>) -> String {
+    match unit {
+        Some(unit) if unit.len() > 2 && unit.chars().all(|c| c.is_ascii_alphabetic()) => {
+            format!("{} {}", format_amount(amount), unit)
+        }
+        Some(unit) => format!("{}{}", format_amount(amount), unit),
+        None => format_amount(amount),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(text: &str) -> (Option<f64>, Option<String>, Option<String>) {
+        let quantity = Quantity::parse(text);
+        assert_eq!(quantity.text, text, "text is kept as entered");
+        (quantity.amount, quantity.unit, quantity.note)
+    }
+
+    fn some(text: &str) -> Option<String> {
+        Some(text.to_string())
+    }
+
+    #[test]
+    fn parses_amounts_and_units() {
+        assert_eq!(parsed("200g"), (Some(200.0), some("g"), None));
+        assert_eq!(parsed("1.5 kg"), (Some(1.5), some("kg"), None));
+        assert_eq!(parsed("2个"), (Some(2.0), some("个"), None));
+        assert_eq!(parsed("3"), (Some(3.0), None, None));
+        assert_eq!(parsed("1千克"), (Some(1.0), some("千克"), None));
+    }
+
+    #[test]
+    fn parses_fractions() {
+        assert_eq!(parsed("1/2 cup"), (Some(0.5), some("cup"), None));
+        assert_eq!(parsed("1 ½ tbsp"), (Some(1.5), some("tbsp"), None));
+        assert_eq!(parsed("1½杯"), (Some(1.5), some("杯"), None));
+        assert_eq!(parsed("半个"), (Some(0.5), some("个"), None));
+    }
+
+    #[test]
+    fn keeps_the_rest_as_note() {
+        assert_eq!(parsed("2个 切块"), (Some(2.0), some("个"), some("切块")));
+        assert_eq!(
+            parsed("2 cloves minced"),
+            (Some(2.0), some("cloves"), some("minced"))
+        );
+        assert_eq!(parsed("适量"), (None, None, some("适量")));
+        assert_eq!(parsed("  少许 "), (None, None, some("少许")));
+        assert_eq!(parsed(""), (None, None, None));
+    }
+
+    #[test]
+    fn leaves_ranges_unparsed() {
+        assert_eq!(parsed("2-3个"), (None, None, some("2-3个")));
+        assert_eq!(parsed("2 ~ 3 cloves"), (None, None, some("2 ~ 3 cloves")));
+        assert_eq!(parsed("1到2勺"), (None, None, some("1到2勺")));
+        assert!(Quantity::parse("2-3个").scale(2.0).is_none());
+        // A dash not followed by an amount is only a note
+        assert_eq!(parsed("2个 - 大"), (Some(2.0), some("个"), some("- 大")));
+    }
+
+    #[test]
+    fn round_trips_through_storage() {
+        for text in [
+            "200g",
+            "1 ½ tbsp",
+            "半个",
+            "适量",
+            "2个 切块",
+            "2-3个",
+            " 3 cups ",
+        ] {
+            let quantity = Quantity::parse(text);
+            let stored = serde_json::to_string(&quantity).unwrap();
+            let read: Quantity = serde_json::from_str(&stored).unwrap();
+            assert_eq!(read.text, text);
+            assert_eq!(read.amount, quantity.amount);
+            assert_eq!(read.unit, quantity.unit);
+            assert_eq!(read.note, quantity.note);
+        }
+    }
+
+    #[test]
+    fn reads_bare_text_and_reparses_stored_ranges() {
+        let read: Quantity = serde_json::from_str(r#""200g""#).unwrap();
+        assert_eq!(
+            (read.amount, read.unit.as_deref()),
+            (Some(200.0), Some("g"))
+        );
+        let read: Quantity = serde_json::from_str(
+            r#"{"amount": 2.0, "unit": null, "note": "-3个", "text": "2-3个"}"#,
+        )
+        .unwrap();
+        assert_eq!((read.amount, read.note.as_deref()), (None, Some("2-3个")));
+    }
+
+    #[test]
+    fn scales_and_rewrites_text() {
+        let scaled = Quantity::parse("1/2 cup 切碎").scale(3.0).unwrap();
+        assert_eq!(scaled.amount, Some(1.5));
+        assert_eq!(scaled.text, "1.5 cup 切碎");
+        assert!(Quantity::parse("适量").scale(2.0).is_none());
+    }
+
+    #[test]
+    fn converts_between_unit_systems() {
+        let converted = Quantity::parse("2 cups")
+            .convert(UnitSystem::Metric)
+            .unwrap();
+        assert_eq!(converted.text, "480ml");
+        let converted = Quantity::parse("1.5kg")
+            .convert(UnitSystem::Imperial)
+            .unwrap();
+        assert_eq!(converted.unit.as_deref(), Some("lb"));
+        assert!(Quantity::parse("2个").convert(UnitSystem::Metric).is_none());
+    }
+
+    #[test]
+    fn converts_amounts_through_density_and_piece_weight() {
+        let egg = Ingredient {
+            name: "鸡蛋".to_string(),
+            category: "蛋奶".to_string(),
+            density: None,
+            piece_weight: Some(50.0),
+        };
+        assert_eq!(
+            convert_amount(1.0, Some("斤"), Some("g"), None),
+            Some(500.0)
+        );
+        assert_eq!(
+            convert_amount(3.0, Some("个"), Some("g"), Some(&egg)),
+            Some(150.0)
+        );
+        assert_eq!(
+            convert_amount(100.0, Some("g"), None, Some(&egg)),
+            Some(2.0)
+        );
+        assert_eq!(
+            convert_amount(1.0, Some("cup"), Some("g"), Some(&egg)),
+            None
+        );
+        assert_eq!(convert_amount(1.0, Some("把"), Some("g"), None), None);
+    }
+
+    #[test]
+    fn formats_amounts() {
+        assert_eq!(format_quantity(1.0 / 3.0, Some("cup")), "0.33 cup");
+        assert_eq!(format_quantity(2.50, Some("g")), "2.5g");
+        assert_eq!(format_quantity(2.0, Some("个")), "2个");
+        assert_eq!(format_quantity(4.0, None), "4");
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    graphql::{Dish, Ingredient, RawRecipeIngredient, ShoppingCategory, ShoppingItem, UnitSystem},
+    quantity::{display_unit, format_quantity, unit_size, Dimension},
+};
+
+/// Recipe line of one dish, with the factor its quantity is scaled by.
+pub(crate) struct Line<'a> {
+    pub dish: &'a str,
+    pub ingredient: &'a RawRecipeIngredient,
+    pub factor: f64,
+}
+
+#[derive(Default)]
+struct Merged {
+    /// Grams over all lines measured by mass
+    mass: Option<f64>,
+    /// Millilitres over all lines measured by volume
+    volume: Option<f64>,
+    /// Summed amounts per other unit, in order of first appearance
+    amounts: Vec<(Option<String>, f64)>,
+    /// Quantities without a leading number, such as "适量"
+    notes: Vec<String>,
+    dishes: Vec<String>,
+}
+
+impl Merged {
+    fn add(&mut self, amount: f64, unit: Option<&str>) {
+        match unit_size(unit) {
+            Some((Dimension::Mass, size)) => *self.mass.get_or_insert(0.0) += amount * size,
+            Some((Dimension::Volume, size)) => *self.volume.get_or_insert(0.0) += amount * size,
+            _ => match self.amounts.iter_mut().find(|(u, _)| u.as_deref() == unit) {
+                Some((_, total)) => *total += amount,
+                None => self.amounts.push((unit.map(str::to_string), amount)),
+            },
+        }
+    }
+
+    /// Folds volumes and counts into the mass when the ingredient says how
+    /// much they weigh. Only done when there is a mass to compare with, a
+    /// list asking for "2个" alone reads better than one asking for "300g".
+    fn fold_into_mass(&mut self, ingredient: Option<&Ingredient>) {
+        let (Some(mass), Some(ingredient)) = (self.mass.as_mut(), ingredient) else {
+            return;
+        };
+        if let Some(density) = ingredient.density {
+            *mass += self.volume.take().unwrap_or(0.0) * density;
+        }
+        if let Some(piece_weight) = ingredient.piece_weight {
+            self.amounts
+                .retain(|(unit, amount)| match unit_size(unit.as_deref()) {
+                    Some((Dimension::Count, size)) => {
+                        *mass += amount * size * piece_weight;
+                        false
+                    }
+                    _ => true,
+                });
+        }
+    }
+
+    fn quantities(self, system: UnitSystem) -> Vec<String> {
+        let base = [
+            (Dimension::Mass, self.mass),
+            (Dimension::Volume, self.volume),
+        ]
+        .into_iter()
+        .filter_map(|(dimension, base)| {
+            let base = base?;
+            let (unit, size) = display_unit(dimension, base, system)?;
+            Some(format_quantity(base / size, Some(unit)))
+        });
+        let others = self
+            .amounts
+            .into_iter()
+            .map(|(unit, amount)| format_quantity(amount, unit.as_deref()));
+        base.chain(others).chain(self.notes).collect()
+    }
+}
+
+/// Groups recipe lines by ingredient category and merges the quantities of
+/// the same ingredient. Masses and volumes are added up in `system`, other
+/// amounts when they share a unit, and quantities without amount are listed
+/// once each. Ingredients missing from `ingredients` end up in a last group
+/// without category.
+pub(crate) fn shopping_list(
+    lines: &[Line],
+    ingredients: &HashMap<String, Ingredient>,
+    system: UnitSystem,
+) -> Vec<ShoppingCategory> {
+    let mut merged: BTreeMap<(Option<&str>, &str), Merged> = BTreeMap::new();
+    for line in lines {
+        let name = line.ingredient.name.as_str();
+        let category = ingredients.get(name).map(|i| i.category.as_str());
+        let item = merged.entry((category, name)).or_default();
+        let quantity = &line.ingredient.quantity;
+        match quantity.amount {
+            Some(amount) => item.add(amount * line.factor, quantity.unit.as_deref()),
+            None => {
+                let note = quantity.text.trim();
+                if !note.is_empty() && !item.notes.iter().any(|n| n == note) {
+                    item.notes.push(note.to_string());
+                }
+            }
+        }
+        if !item.dishes.iter().any(|d| d == line.dish) {
+            item.dishes.push(line.dish.to_string());
+        }
+    }
+
+    let mut categories: Vec<ShoppingCategory> = vec![];
+    for ((category, name), mut item) in merged {
+        item.fold_into_mass(ingredients.get(name));
+        let shopping_item = ShoppingItem {
+            name: name.to_string(),
+            dishes: std::mem::take(&mut item.dishes),
+            quantities: item.quantities(system),
+        };
+        match categories.last_mut() {
+            Some(last) if last.category.as_deref() == category => last.items.push(shopping_item),
+            _ => categories.push(ShoppingCategory {
+                category: category.map(str::to_string),
+                items: vec![shopping_item],
+            }),
+        }
+    }
+    // `None` sorts first in the map, move it to the end
+    if categories.first().is_some_and(|c| c.category.is_none()) {
+        categories.rotate_left(1);
+    }
+    categories
+}
+
+/// Names in `requested` without a dish in `found`, in order.
+pub(crate) fn missing_dishes(requested: &[String], found: &HashMap<String, Dish>) -> Vec<String> {
+    requested
+        .iter()
+        .filter(|name| !found.contains_key(*name))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphql::Quantity;
+
+    fn line(name: &str, quantity: &str) -> RawRecipeIngredient {
+        RawRecipeIngredient {
+            name: name.to_string(),
+            quantity: Quantity::parse(quantity),
+        }
+    }
+
+    fn ingredient(name: &str, category: &str) -> (String, Ingredient) {
+        let ingredient = Ingredient {
+            name: name.to_string(),
+            category: category.to_string(),
+            density: None,
+            piece_weight: None,
+        };
+        (name.to_string(), ingredient)
+    }
+
+    /// Category, name, quantities and dishes of an item on the list.
+    type Listed = (Option<String>, String, Vec<String>, Vec<String>);
+
+    /// Shopping list of `recipes`, each a dish name and its recipe lines
+    /// scaled by a factor.
+    fn list(
+        recipes: &[(&str, f64, Vec<RawRecipeIngredient>)],
+        ingredients: &HashMap<String, Ingredient>,
+        system: UnitSystem,
+    ) -> Vec<Listed> {
+        let lines: Vec<Line> = recipes
+            .iter()
+            .flat_map(|(dish, factor, recipe)| {
+                recipe.iter().map(|ingredient| Line {
+                    dish,
+                    ingredient,
+                    factor: *factor,
+                })
+            })
+            .collect();
+        shopping_list(&lines, ingredients, system)
+            .into_iter()
+            .flat_map(|category| {
+                category.items.into_iter().map(move |item| {
+                    let category = category.category.clone();
+                    (category, item.name, item.quantities, item.dishes)
+                })
+            })
+            .collect()
+    }
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn masses_and_volumes_add_up_across_units_and_dishes() {
+        let ingredients = HashMap::from([ingredient("猪肉", "肉类"), ingredient("牛奶", "乳品")]);
+        let recipes = [
+            (
+                "红烧肉",
+                1.0,
+                vec![line("猪肉", "600g"), line("牛奶", "200ml")],
+            ),
+            (
+                "回锅肉",
+                2.0,
+                vec![line("猪肉", "0.3kg"), line("牛奶", "1l")],
+            ),
+        ];
+        let list = list(&recipes, &ingredients, UnitSystem::Metric);
+        assert_eq!(
+            list,
+            [
+                (
+                    Some("乳品".to_string()),
+                    "牛奶".to_string(),
+                    strings(&["2.2l"]),
+                    strings(&["红烧肉", "回锅肉"])
+                ),
+                (
+                    Some("肉类".to_string()),
+                    "猪肉".to_string(),
+                    strings(&["1.2kg"]),
+                    strings(&["红烧肉", "回锅肉"])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_amounts_add_up_per_unit_and_notes_are_listed_once() {
+        let ingredients = HashMap::from([ingredient("葱", "蔬菜")]);
+        let recipes = [
+            ("a", 1.0, vec![line("葱", "2根"), line("葱", "适量")]),
+            (
+                "b",
+                1.0,
+                vec![line("葱", "1把"), line("葱", "1根"), line("葱", "适量")],
+            ),
+        ];
+        let list = list(&recipes, &ingredients, UnitSystem::Metric);
+        assert_eq!(list[0].2, strings(&["3根", "1把", "适量"]));
+    }
+
+    #[test]
+    fn counts_fold_into_mass_when_pieces_have_a_weight() {
+        let (name, mut egg) = ingredient("鸡蛋", "蛋类");
+        egg.piece_weight = Some(50.0);
+        let ingredients = HashMap::from([(name, egg)]);
+        let with_mass = [("a", 1.0, vec![line("鸡蛋", "100g"), line("鸡蛋", "2个")])];
+        let list_with_mass = list(&with_mass, &ingredients, UnitSystem::Metric);
+        assert_eq!(list_with_mass[0].2, strings(&["200g"]));
+        // Without a mass to add to, the count reads better as it is
+        let counted = [("a", 1.0, vec![line("鸡蛋", "2个")])];
+        assert_eq!(
+            list(&counted, &ingredients, UnitSystem::Metric)[0].2,
+            strings(&["2个"])
+        );
+    }
+
+    #[test]
+    fn imperial_lists_convert_masses() {
+        let ingredients = HashMap::from([ingredient("面粉", "主食")]);
+        let recipes = [("a", 1.0, vec![line("面粉", "1kg")])];
+        let list = list(&recipes, &ingredients, UnitSystem::Imperial);
+        assert_eq!(list[0].2, strings(&["2.2lb"]));
+    }
+
+    #[test]
+    fn ingredients_without_category_come_last() {
+        let ingredients = HashMap::from([ingredient("葱", "蔬菜"), ingredient("猪肉", "肉类")]);
+        let recipes = [(
+            "a",
+            1.0,
+            vec![line("神秘酱", "1勺"), line("葱", "1根"), line("猪肉", "1g")],
+        )];
+        let list = list(&recipes, &ingredients, UnitSystem::Metric);
+        let order: Vec<_> = list
+            .iter()
+            .map(|(category, name, _, _)| (category.as_deref(), name.as_str()))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                (Some("肉类"), "猪肉"),
+                (Some("蔬菜"), "葱"),
+                (None, "神秘酱")
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_dishes_are_reported_in_order() {
+        let found = HashMap::from([(
+            "b".to_string(),
+            Dish {
+                name: "b".to_string(),
+                ..Dish::default()
+            },
+        )]);
+        let requested = strings(&["c", "b", "a"]);
+        assert_eq!(missing_dishes(&requested, &found), strings(&["c", "a"]));
+    }
+}